@@ -0,0 +1,456 @@
+// Copyright © 2026 David Caldwell <david@porkrind.org>
+
+use std::error::Error;
+
+use pbkdf2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::request::{self, FromRequest, Request};
+use sha2::Digest;
+
+use crate::db::{self, Db};
+use crate::wrap;
+
+// Clients authenticate with API tokens that `syncron token create` hands out. Only the sha256 of a token is kept, so
+// the db can't be used to get them back. Until the first token is created nothing needs one, so an existing setup keeps
+// working until someone decides to lock it down.
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Token {
+    pub name:      String,
+    pub user:      Option<String>, // Scopes. A token with neither can be used for anything.
+    pub namespace: Option<String>,
+    pub created:   chrono::DateTime<chrono::Local>,
+    pub last_used: Option<chrono::DateTime<chrono::Local>>,
+    pub revoked:   Option<chrono::DateTime<chrono::Local>>,
+}
+
+fn hash(secret: &str) -> String {
+    base16ct::lower::encode_string(&sha2::Sha256::digest(secret.trim().as_bytes()))
+}
+
+impl Token {
+    // Returns the token along with the secret that goes to the client. This is the only time the secret is around.
+    pub async fn create(db: &Db, name: &str, user: Option<&str>, namespace: Option<&str>) -> Result<(Token, String), Box<dyn Error>> {
+        let mut random = [0; 32];
+        getrandom::getrandom(&mut random)?;
+        let secret = format!("syncron_{}", base16ct::lower::encode_string(&random));
+        let (hash, now) = (hash(&secret), chrono::Local::now().timestamp_millis());
+        sqlx::query!("INSERT INTO token (name, hash, user, namespace, created) VALUES (?, ?, ?, ?, ?)", name, hash, user, namespace, now)
+            .execute(db.sql()).await.map_err(|e| wrap(&e, &format!("Couldn't create token {:?} (is the name already taken?)", name)))?;
+        Ok((Token { name: name.to_string(), user: user.map(str::to_string), namespace: namespace.map(str::to_string),
+                    created: db::time_from_timestamp_ms(now), last_used: None, revoked: None },
+            secret))
+    }
+
+    pub async fn list(db: &Db) -> Result<Vec<Token>, Box<dyn Error>> {
+        Ok(sqlx::query!("SELECT name, user, namespace, created, last_used, revoked FROM token ORDER BY name")
+            .fetch_all(db.sql()).await.map_err(|e| wrap(&e, "list tokens"))?.into_iter()
+            .map(|t| Token { name: t.name, user: t.user, namespace: t.namespace,
+                             created: db::time_from_timestamp_ms(t.created),
+                             last_used: t.last_used.map(db::time_from_timestamp_ms),
+                             revoked: t.revoked.map(db::time_from_timestamp_ms) })
+            .collect())
+    }
+
+    // Returns false if there's no such token (or it was already revoked).
+    pub async fn revoke(db: &Db, name: &str) -> Result<bool, Box<dyn Error>> {
+        let now = chrono::Local::now().timestamp_millis();
+        Ok(sqlx::query!("UPDATE token SET revoked = ? WHERE name = ? AND revoked IS NULL", now, name)
+            .execute(db.sql()).await.map_err(|e| wrap(&e, "revoke token"))?.rows_affected() > 0)
+    }
+
+    // Revoked tokens still count--revoking the last one shouldn't quietly open everything back up.
+    pub async fn required(db: &Db) -> Result<bool, Box<dyn Error>> {
+        Ok(sqlx::query!("SELECT EXISTS(SELECT 1 FROM token) AS \"any!: bool\"").fetch_one(db.sql()).await.map_err(|e| wrap(&e, "any tokens"))?.any)
+    }
+
+    // The token for `secret`, if it's good.
+    pub async fn check(db: &Db, secret: &str) -> Result<Option<Token>, Box<dyn Error>> {
+        let (hash, now) = (hash(secret), chrono::Local::now().timestamp_millis());
+        Ok(sqlx::query!(r#"UPDATE token SET last_used = ? WHERE hash = ? AND revoked IS NULL
+                            RETURNING name, user, namespace, created, last_used AS "last_used!: i64""#, now, hash)
+            .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "check token"))?
+            .map(|t| Token { name: t.name, user: t.user, namespace: t.namespace,
+                             created: db::time_from_timestamp_ms(t.created),
+                             last_used: Some(db::time_from_timestamp_ms(t.last_used)),
+                             revoked: None }))
+    }
+
+    pub fn allows(&self, user: &str, job_id: &str) -> bool {
+        self.user.as_deref().is_none_or(|u| u == user) && self.namespace.as_deref().is_none_or(|ns| job_id.starts_with(ns))
+    }
+}
+
+// Request guard for the client API (/run/*). `None` inside means tokens aren't being used (yet).
+#[derive(Debug)]
+pub struct ClientAuth(Option<Token>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientAuth {
+    type Error = String;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let Some(db) = req.rocket().state::<Db>() else { return request::Outcome::Error((Status::InternalServerError, "No db".into())) };
+        match Token::required(db).await {
+            Ok(false) => return request::Outcome::Success(ClientAuth(None)),
+            Ok(true)  => {},
+            Err(e)    => return request::Outcome::Error((Status::InternalServerError, e.to_string())),
+        }
+        let Some(secret) = req.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Bearer ")) else {
+            return request::Outcome::Error((Status::Unauthorized, "Missing API token".into()));
+        };
+        match Token::check(db, secret).await {
+            Ok(Some(token)) => request::Outcome::Success(ClientAuth(Some(token))),
+            Ok(None)        => request::Outcome::Error((Status::Unauthorized, "Bad API token".into())),
+            Err(e)          => request::Outcome::Error((Status::InternalServerError, e.to_string())),
+        }
+    }
+}
+
+impl ClientAuth {
+    pub fn check(&self, user: &str, job_id: &str) -> Result<(), Box<dyn Error>> {
+        match self.0 {
+            Some(ref token) if !token.allows(user, job_id) => Err(format!("Token {:?} can't be used for {}/{}", token.name, user, job_id))?,
+            _                                              => Ok(()),
+        }
+    }
+
+    // Like check(), but for a run that's about to be created. The old id of a renamed or merged job leads to the job
+    // it belongs to now, so that's the id the token has to be good for.
+    pub async fn check_new(&self, db: &Db, user: &str, job_id: &str) -> Result<(), Box<dyn Error>> {
+        let job_id = db::Job::alias(db, user, job_id).await?.unwrap_or_else(|| job_id.to_string());
+        self.check(user, &job_id)
+    }
+
+    // Like db::Run::from_client_id(), but only if the token is good for the run's job.
+    pub async fn run(&self, db: &Db, id: u128) -> Result<db::Run, Box<dyn Error>> {
+        let run = db::Run::from_client_id(db, id).await?;
+        self.check(&run.job.user, &run.job.id)?;
+        Ok(run)
+    }
+}
+
+// People log in to the web interface with local accounts. A good login gets a session cookie, and like tokens, only
+// the session's sha256 is kept. Just like tokens, nothing needs an account until the first one is created.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,   // Can look at the jobs it can see
+    Operator, // ...and change their settings, prune them and re-judge their runs
+    Admin,    // ...and change the global settings
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s { "viewer"   => Ok(Role::Viewer),
+                  "operator" => Ok(Role::Operator),
+                  "admin"    => Ok(Role::Admin),
+                  _          => Err(format!("Bad role: {} (should be viewer, operator or admin)", s)) }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self { Role::Viewer => "viewer", Role::Operator => "operator", Role::Admin => "admin" })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Account {
+    #[serde(skip)]
+    account_id:     i64,
+    pub name:       String,
+    pub role:       Role,
+    pub users:      Vec<String>, // The syncron users whose jobs this account can see. Empty means all of them.
+    pub created:    chrono::DateTime<chrono::Local>,
+    pub last_login: Option<chrono::DateTime<chrono::Local>>,
+}
+
+const PASSWORD_ROUNDS: u32 = if cfg!(test) { 1_000 } else { 100_000 }; // Unoptimized test builds are really slow at this
+const SESSION_IDLE_MS: i64 = 14 * 24 * 60 * 60 * 1000; // Sessions that go unused this long have to log in again
+pub const SESSION_COOKIE: &str = "syncron_session";
+
+fn hash_password(password: &str) -> Result<String, Box<dyn Error>> {
+    let mut salt = [0; 16];
+    getrandom::getrandom(&mut salt)?;
+    let salt = SaltString::b64_encode(&salt).map_err(|e| wrap(&e, "salt"))?;
+    let params = pbkdf2::Params { rounds: PASSWORD_ROUNDS, output_length: 32 };
+    Ok(pbkdf2::Pbkdf2.hash_password_customized(password.as_bytes(), Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()), None, params, &salt)
+       .map_err(|e| wrap(&e, "hash password"))?.to_string())
+}
+
+fn check_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| pbkdf2::Pbkdf2.verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+impl Account {
+    pub async fn create(db: &Db, name: &str, password: &str, role: Role, users: &[String]) -> Result<Account, Box<dyn Error>> {
+        let (password, role_str, now) = (hash_password(password)?, role.to_string(), chrono::Local::now().timestamp_millis());
+        let mut transaction = db.sql().begin().await.map_err(|e| wrap(&e, "account transaction"))?;
+        let account_id = sqlx::query!("INSERT INTO account (name, password, role, created) VALUES (?, ?, ?, ?) RETURNING account_id", name, password, role_str, now)
+            .fetch_one(&mut *transaction).await.map_err(|e| wrap(&e, &format!("Couldn't create account {:?} (is the name already taken?)", name)))?.account_id;
+        for user in users {
+            sqlx::query!("INSERT INTO account_user (account_id, user) VALUES (?, ?) ON CONFLICT DO NOTHING", account_id, user)
+                .execute(&mut *transaction).await.map_err(|e| wrap(&e, "account user"))?;
+        }
+        transaction.commit().await.map_err(|e| wrap(&e, "account commit"))?;
+        Ok(Account { account_id, name: name.to_string(), role, users: users.to_vec(), created: db::time_from_timestamp_ms(now), last_login: None })
+    }
+
+    async fn users(db: &Db, account_id: i64) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(sqlx::query!("SELECT user FROM account_user WHERE account_id = ? ORDER BY user", account_id)
+            .fetch_all(db.sql()).await.map_err(|e| wrap(&e, "account users"))?.into_iter().map(|u| u.user).collect())
+    }
+
+    pub async fn list(db: &Db) -> Result<Vec<Account>, Box<dyn Error>> {
+        let rows = sqlx::query!("SELECT account_id, name, role, created, last_login FROM account ORDER BY name")
+            .fetch_all(db.sql()).await.map_err(|e| wrap(&e, "list accounts"))?;
+        let mut accounts = vec![];
+        for a in rows {
+            accounts.push(Account { users: Account::users(db, a.account_id).await?,
+                                    account_id: a.account_id, name: a.name, role: a.role.parse()?,
+                                    created: db::time_from_timestamp_ms(a.created),
+                                    last_login: a.last_login.map(db::time_from_timestamp_ms) });
+        }
+        Ok(accounts)
+    }
+
+    async fn from_id(db: &Db, account_id: i64) -> Result<Option<Account>, Box<dyn Error>> {
+        let Some(a) = sqlx::query!("SELECT name, role, created, last_login FROM account WHERE account_id = ?", account_id)
+            .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "account"))? else { return Ok(None) };
+        Ok(Some(Account { users: Account::users(db, account_id).await?,
+                          account_id, name: a.name, role: a.role.parse()?,
+                          created: db::time_from_timestamp_ms(a.created),
+                          last_login: a.last_login.map(db::time_from_timestamp_ms) }))
+    }
+
+    // Logs everyone using the account out, too, in case that's why the password is being changed.
+    pub async fn set_password(db: &Db, name: &str, password: &str) -> Result<bool, Box<dyn Error>> {
+        let password = hash_password(password)?;
+        let mut transaction = db.sql().begin().await.map_err(|e| wrap(&e, "password transaction"))?;
+        let Some(account) = sqlx::query!("UPDATE account SET password = ? WHERE name = ? RETURNING account_id", password, name)
+            .fetch_optional(&mut *transaction).await.map_err(|e| wrap(&e, "set password"))? else { return Ok(false) };
+        sqlx::query!("DELETE FROM session WHERE account_id = ?", account.account_id)
+            .execute(&mut *transaction).await.map_err(|e| wrap(&e, "password sessions"))?;
+        transaction.commit().await.map_err(|e| wrap(&e, "password commit"))?;
+        Ok(true)
+    }
+
+    // Returns false if there's no such account. Its sessions go with it.
+    pub async fn delete(db: &Db, name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(sqlx::query!("DELETE FROM account WHERE name = ?", name)
+            .execute(db.sql()).await.map_err(|e| wrap(&e, "delete account"))?.rows_affected() > 0)
+    }
+
+    pub async fn required(db: &Db) -> Result<bool, Box<dyn Error>> {
+        Ok(sqlx::query!("SELECT EXISTS(SELECT 1 FROM account) AS \"any!: bool\"").fetch_one(db.sql()).await.map_err(|e| wrap(&e, "any accounts"))?.any)
+    }
+
+    // Returns the account and a new session secret for its cookie, or None if the name or password is wrong.
+    pub async fn login(db: &Db, name: &str, password: &str) -> Result<Option<(Account, String)>, Box<dyn Error>> {
+        let Some(a) = sqlx::query!("SELECT account_id, password FROM account WHERE name = ?", name)
+            .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "login"))? else { return Ok(None) };
+        if !check_password(password, &a.password) { return Ok(None) }
+        let mut random = [0; 32];
+        getrandom::getrandom(&mut random)?;
+        let secret = base16ct::lower::encode_string(&random);
+        let (hash, now) = (hash(&secret), chrono::Local::now().timestamp_millis());
+        sqlx::query!("INSERT INTO session (account_id, hash, created, last_used) VALUES (?, ?, ?, ?)", a.account_id, hash, now, now)
+            .execute(db.sql()).await.map_err(|e| wrap(&e, "create session"))?;
+        sqlx::query!("UPDATE account SET last_login = ? WHERE account_id = ?", now, a.account_id)
+            .execute(db.sql()).await.map_err(|e| wrap(&e, "last login"))?;
+        Ok(Account::from_id(db, a.account_id).await?.map(|account| (account, secret)))
+    }
+
+    // The account that owns the session, if the session is still good.
+    pub async fn from_session(db: &Db, secret: &str) -> Result<Option<Account>, Box<dyn Error>> {
+        let (hash, now) = (hash(secret), chrono::Local::now().timestamp_millis());
+        let idle_since = now - SESSION_IDLE_MS;
+        let Some(session) = sqlx::query!("UPDATE session SET last_used = ? WHERE hash = ? AND last_used > ? RETURNING account_id", now, hash, idle_since)
+            .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "check session"))? else { return Ok(None) };
+        Account::from_id(db, session.account_id).await
+    }
+
+    pub async fn logout(db: &Db, secret: &str) -> Result<(), Box<dyn Error>> {
+        let (hash, idle_since) = (hash(secret), chrono::Local::now().timestamp_millis() - SESSION_IDLE_MS);
+        sqlx::query!("DELETE FROM session WHERE hash = ? OR last_used <= ?", hash, idle_since) // Clean up stale ones while we're here
+            .execute(db.sql()).await.map_err(|e| wrap(&e, "logout"))?;
+        Ok(())
+    }
+
+    pub fn can_see(&self, user: &str) -> bool {
+        self.users.is_empty() || self.users.iter().any(|u| u == user)
+    }
+}
+
+// The session cookie is SameSite=Strict, so other sites can't get a browser to make requests with it.
+pub fn session_cookie(secret: String) -> Cookie<'static> {
+    Cookie::build((SESSION_COOKIE, secret)).http_only(true).same_site(SameSite::Strict).path("/")
+        .max_age(rocket::time::Duration::milliseconds(SESSION_IDLE_MS)).build()
+}
+
+pub fn session_secret(cookies: &CookieJar<'_>) -> Option<String> {
+    cookies.get(SESSION_COOKIE).map(|c| c.value().to_string())
+}
+
+// Request guard for the web API. `None` inside means accounts aren't being used (yet), so everyone can do everything.
+#[derive(Debug)]
+pub struct WebAuth(Option<Account>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebAuth {
+    type Error = String;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let Some(db) = req.rocket().state::<Db>() else { return request::Outcome::Error((Status::InternalServerError, "No db".into())) };
+        match Account::required(db).await {
+            Ok(false) => return request::Outcome::Success(WebAuth(None)),
+            Ok(true)  => {},
+            Err(e)    => return request::Outcome::Error((Status::InternalServerError, e.to_string())),
+        }
+        let Some(secret) = session_secret(req.cookies()) else { return request::Outcome::Error((Status::Unauthorized, "Not logged in".into())) };
+        match Account::from_session(db, &secret).await {
+            Ok(Some(account)) => request::Outcome::Success(WebAuth(Some(account))),
+            Ok(None)          => request::Outcome::Error((Status::Unauthorized, "Session expired".into())),
+            Err(e)            => request::Outcome::Error((Status::InternalServerError, e.to_string())),
+        }
+    }
+}
+
+impl WebAuth {
+    pub fn account(&self) -> Option<&Account> { self.0.as_ref() }
+    pub fn role(&self)    -> Role { self.0.as_ref().map(|a| a.role).unwrap_or(Role::Admin) }
+
+    pub fn can_see(&self, user: &str) -> bool {
+        self.0.as_ref().is_none_or(|a| a.can_see(user))
+    }
+
+    // Like db::Job::new(), but jobs the account can't see don't exist.
+    pub async fn job(&self, db: &Db, user: &str, job_id: &str) -> Result<Option<db::Job>, Box<dyn Error>> {
+        if !self.can_see(user) { return Ok(None) }
+        db::Job::new(db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))
+    }
+
+    pub async fn deleted_job(&self, db: &Db, user: &str, job_id: &str) -> Result<Option<db::Job>, Box<dyn Error>> {
+        if !self.can_see(user) { return Ok(None) }
+        db::Job::deleted(db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job deleted"))
+    }
+}
+
+async fn web_auth_with_role(req: &Request<'_>, role: Role) -> request::Outcome<WebAuth, String> {
+    match req.guard::<WebAuth>().await {
+        request::Outcome::Success(auth) if auth.role() < role => request::Outcome::Error((Status::Forbidden, format!("This needs the {} role", role))),
+        outcome                                               => outcome,
+    }
+}
+
+// Request guards for the parts of the web API that change things.
+#[derive(Debug)]
+pub struct Operator(pub WebAuth);
+#[derive(Debug)]
+pub struct Admin; // The global settings aren't about anyone's jobs, so there's nothing to hang on to
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Operator {
+    type Error = String;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        web_auth_with_role(req, Role::Operator).await.map(Operator)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = String;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        web_auth_with_role(req, Role::Admin).await.map(|_| Admin)
+    }
+}
+
+// Either a logged in person or a client (with a good token, once there are tokens). Clients need some of the web API
+// (the global settings).
+#[derive(Debug)]
+pub struct AnyAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AnyAuth {
+    type Error = String;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match req.guard::<WebAuth>().await {
+            request::Outcome::Success(_) => request::Outcome::Success(AnyAuth),
+            _                            => req.guard::<ClientAuth>().await.map(|_| AnyAuth),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tokens() {
+        let dir = tempfile::Builder::new().prefix("syncron-test").tempdir().unwrap();
+        let db = Db::new(dir.path()).await.expect("db");
+        assert!(!Token::required(&db).await.expect("required"));
+
+        let (token, secret) = Token::create(&db, "web1", Some("backup"), Some("web1-")).await.expect("token created");
+        assert!(Token::create(&db, "web1", None, None).await.is_err());
+        assert!(Token::required(&db).await.expect("required"));
+        assert_eq!(Token::check(&db, &secret).await.expect("checked").map(|t| t.name), Some(token.name.clone()));
+        assert_eq!(Token::check(&db, "syncron_nope").await.expect("checked"), None);
+        assert!(Token::list(&db).await.expect("list")[0].last_used.is_some());
+
+        assert!(token.allows("backup", "web1-nightly"));
+        assert!(!token.allows("root", "web1-nightly"));
+        assert!(!token.allows("backup", "web2-nightly"));
+
+        // An old id that looks like it's in the token's namespace doesn't count if the job has moved out of it
+        let job = db::Job::ensure(&db, "backup", "Web1 Old", None).await.expect("job");
+        job.rename(None, Some("web2-new")).await.expect("renamed");
+        let auth = ClientAuth(Some(token.clone()));
+        assert!(auth.check_new(&db, "backup", "web1-old").await.is_err());
+        assert!(auth.check_new(&db, "backup", "web1-nightly").await.is_ok());
+
+        assert!(Token::revoke(&db, "web1").await.expect("revoked"));
+        assert!(!Token::revoke(&db, "web1").await.expect("already revoked"));
+        assert_eq!(Token::check(&db, &secret).await.expect("checked"), None);
+        assert!(Token::required(&db).await.expect("still required"));
+    }
+
+    #[tokio::test]
+    async fn test_accounts() {
+        let dir = tempfile::Builder::new().prefix("syncron-test").tempdir().unwrap();
+        let db = Db::new(dir.path()).await.expect("db");
+        assert!(!Account::required(&db).await.expect("required"));
+
+        let account = Account::create(&db, "alice", "hunter2", Role::Operator, &["backup".into()]).await.expect("account created");
+        assert!(Account::create(&db, "alice", "password", Role::Viewer, &[]).await.is_err());
+        assert!(Account::required(&db).await.expect("required"));
+        assert!(account.can_see("backup"));
+        assert!(!account.can_see("root"));
+
+        assert_eq!(Account::login(&db, "alice", "wrong").await.expect("login"), None);
+        assert_eq!(Account::login(&db, "bob", "hunter2").await.expect("login"), None);
+        let (logged_in, secret) = Account::login(&db, "alice", "hunter2").await.expect("login").expect("good password");
+        assert!(logged_in.last_login.is_some());
+        assert_eq!(Account::from_session(&db, &secret).await.expect("session").map(|a| (a.name, a.role, a.users)),
+                   Some(("alice".into(), Role::Operator, vec!["backup".into()])));
+        assert_eq!(Account::from_session(&db, "nope").await.expect("session"), None);
+
+        // Changing the password logs everyone out
+        assert!(Account::set_password(&db, "alice", "correct horse").await.expect("set password"));
+        assert_eq!(Account::from_session(&db, &secret).await.expect("session"), None);
+        let (_, secret) = Account::login(&db, "alice", "correct horse").await.expect("login").expect("new password");
+        Account::logout(&db, &secret).await.expect("logout");
+        assert_eq!(Account::from_session(&db, &secret).await.expect("session"), None);
+
+        assert!(Account::delete(&db, "alice").await.expect("deleted"));
+        assert!(!Account::delete(&db, "alice").await.expect("already deleted"));
+        assert!(Account::list(&db).await.expect("list").is_empty());
+
+        assert!(Role::Viewer < Role::Operator && Role::Operator < Role::Admin);
+        assert_eq!("admin".parse::<Role>(), Ok(Role::Admin));
+        assert!("root".parse::<Role>().is_err());
+    }
+}
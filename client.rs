@@ -5,12 +5,14 @@ use std::ffi::OsString;
 use std::os::unix::process::ExitStatusExt;
 
 use reqwest::{header,Url};
-use reqwest::header::{CONTENT_TYPE, ACCEPT};
+use reqwest::header::{CONTENT_TYPE, ACCEPT, AUTHORIZATION};
 use rocket::response::stream::stream; // Rocket exports these so we don't need them in our Cargo.toml
 use rocket::futures::stream::Stream;
 
 use crate::{db, serve};
 use crate::maybe_utf8::MaybeUTF8;
+use crate::redact::{Redactor, StreamMasker};
+use crate::spool::SpoolRun;
 
 #[path="server-sent-events.rs"]
 mod server_sent_events;
@@ -18,24 +20,183 @@ mod server_sent_events;
 #[derive(Debug)]
 pub struct Job {
     id:       String,
+    run_id:   String,
+    req:      serve::CreateRunReq,
+    pub retry: db::RetrySettings, // What the server has for the job. --retries and --retry-delay override these.
+    pub success: db::SuccessPolicy,
+    redactor: Redactor,
     timeout:  Option<std::time::Duration>,
+    kill_after: std::time::Duration,
+    tee:      Tee,
     cmd:      Command,
     api:      Api,
 }
 
+// The job can tell us things by writing lines (see serve::Control) to the unix socket in $SYNCRON_CONTROL. `syncron
+// control` does that for shell scripts. The socket lives in a temporary directory that goes away with this.
+pub struct ControlSocket {
+    dir:      tempfile::TempDir,
+    listener: tokio::net::UnixListener,
+}
+
+impl ControlSocket {
+    fn new() -> Result<ControlSocket, Box<dyn Error>> {
+        let dir = tempfile::Builder::new().prefix("syncron-").tempdir()?;
+        let listener = tokio::net::UnixListener::bind(dir.path().join("control")).map_err(|e| crate::wrap(e, "bind control socket"))?;
+        Ok(ControlSocket { dir, listener })
+    }
+
+    fn path(&self) -> std::path::PathBuf { self.dir.path().join("control") }
+
+    // Never returns. Anything the job (or anything it started) says gets parsed and passed along to `messages`.
+    async fn listen(&self, messages: tokio::sync::mpsc::UnboundedSender<serve::Control>) {
+        loop {
+            let stream = match self.listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e)          => { warn!("Control socket accept failed: {}", e); tokio::time::sleep(std::time::Duration::from_secs(1)).await; continue },
+            };
+            let messages = messages.clone();
+            tokio::spawn(async move {
+                use tokio::io::AsyncBufReadExt;
+                let mut lines = tokio::io::BufReader::new(stream).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.trim().is_empty() { continue }
+                    match line.parse::<serve::Control>() {
+                        Ok(msg) => if messages.send(msg).is_err() { break },
+                        Err(e)  => warn!("Ignoring message from job: {}", e),
+                    }
+                }
+            });
+        }
+    }
+}
+
+// The job can leave files in $SYNCRON_ARTIFACTS (reports, dumps, screenshots...) and they get uploaded and attached to
+// the run once it's done. A failed upload gets warned about but never fails the run. Returns how many made it.
+pub async fn upload_artifacts(api: &Api, id: &str, dir: &std::path::Path) -> usize {
+    let artifacts = match db::list_artifacts(dir) {
+        Ok(artifacts) => artifacts,
+        Err(e)        => { warn!("Couldn't look for artifacts: {}", e); return 0 },
+    };
+    let mut uploaded = 0;
+    for artifact in artifacts {
+        // The server won't take dotfiles (it's the same rule it uses for any path in a URL)
+        if artifact.name.split('/').any(|part| part.starts_with('.')) {
+            warn!("Not uploading artifact {}: names starting with '.' aren't allowed", artifact.name);
+            continue;
+        }
+        let path = dir.join(&artifact.name);
+        let upload = async {
+            let file = tokio::fs::File::open(&path).await?;
+            let url_path = artifact.name.split('/').map(|part| part.bytes().map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+                _                                                                 => format!("%{:02X}", b),
+            }).collect::<String>()).collect::<Vec<_>>().join("/");
+            api.post_stream(&format!("/run/{}/artifact/{}", id, url_path), reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(file))).await
+        };
+        match upload.await {
+            Ok(_)  => { debug!("Uploaded artifact {} ({} bytes)", artifact.name, artifact.size); uploaded += 1 },
+            Err(e) => warn!("Couldn't upload artifact {}: {}", artifact.name, e),
+        }
+    }
+    uploaded
+}
+
+// The job can write Markdown to $SYNCRON_SUMMARY and it shows up at the top of the run's page. Like artifacts, a
+// failed upload just gets warned about.
+pub async fn upload_summary(api: &Api, id: &str, path: &std::path::Path, redactor: Option<&Redactor>) {
+    let mut summary = match tokio::fs::read(path).await {
+        Ok(summary) if summary.iter().all(u8::is_ascii_whitespace) => return,
+        Ok(summary) => summary,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => { warn!("Couldn't read summary {}: {}", path.to_string_lossy(), e); return },
+    };
+    if let Some(redactor) = redactor { redactor.mask(&mut summary) }
+    if let Err(e) = api.post(&format!("/run/{}/summary", id), &summary).await {
+        warn!("Couldn't upload summary: {}", e);
+    }
+}
+
+// For `syncron control`. Does nothing outside of `syncron exec` so jobs that use it still run fine on their own.
+pub fn send_control(message: &str) -> Result<(), Box<dyn Error>> {
+    use std::io::Write;
+    message.parse::<serve::Control>()?; // Catch typos here instead of in the client's log
+    let Some(path) = std::env::var_os("SYNCRON_CONTROL").filter(|p| !p.is_empty()) else {
+        debug!("SYNCRON_CONTROL isn't set--not running under syncron exec?");
+        return Ok(());
+    };
+    if let Err(e) = std::os::unix::net::UnixStream::connect(&path).and_then(|mut socket| socket.write_all(format!("{}\n", message).as_bytes())) {
+        warn!("Couldn't send to {}: {}", path.to_string_lossy(), e); // Not worth failing the job over
+    }
+    Ok(())
+}
+
+// Whether the job's output also goes to our own stdout/stderr (and so to cron mail), not just to the server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tee {
+    Never,
+    Always,
+    OnFailure, // Held back until the job finishes and then only shown if it failed
+}
+
+impl std::str::FromStr for Tee {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s { "never"      => Ok(Tee::Never),
+                  "always"     => Ok(Tee::Always),
+                  "on-failure" => Ok(Tee::OnFailure),
+                  _            => Err(format!("Bad tee mode: {}", s)) }
+    }
+}
+
 impl Job {
-    pub async fn new(server_url: Url, user: &str, name: &str, id: Option<&str>, timeout: Option<std::time::Duration>, cmd: &Command) -> Result<Job, Box<dyn Error>> {
+    // Returns None if the server says to skip this run (the job's overlap setting is "skip" and a previous run is still going).
+    pub async fn new(server_url: Url, req: serve::CreateRunReq, timeout: Option<std::time::Duration>, kill_after: std::time::Duration, tee: Tee, cmd: &Command) -> Result<Option<Job>, Box<dyn Error>> {
+        let api = Api::new(server_url)?;
+        // Older servers don't have redaction settings, so use the defaults.
+        let settings: Option<serve::Settings> = serde_json::from_str(&api.get("/settings").await?).ok();
+        let redactor = Redactor::new(&settings.and_then(|s| s.redaction).unwrap_or_default(), &req.env)?;
+        let req = serve::CreateRunReq { env: redactor.env(&req.env), ..req };
+        let req_json = serde_json::to_string(&req)?;
+        let resp = loop {
+            let resp: serve::CreateRunResp = serde_json::from_str(&api.post("/run/create", req_json.as_bytes()).await?)?;
+            if resp.action != serve::StartAction::Wait { break resp }
+            info!("A previous run of {} is still going. Waiting for it to finish.", resp.job_id);
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        };
+        if resp.action == serve::StartAction::Skip {
+            return Ok(None);
+        }
+        Ok(Some(Job { id:resp.id, run_id: resp.run_id, req, retry: resp.retry, success: resp.success, redactor, timeout, kill_after, tee, cmd:cmd.to_owned(), api }))
+    }
+
+    // Starts another attempt after this one failed. The server records it as a retry of the first attempt.
+    pub async fn retry(&self) -> Result<Job, Box<dyn Error>> {
+        let req = serve::CreateRunReq { attempt_of: Some(self.req.attempt_of.clone().unwrap_or_else(|| self.run_id.clone())),
+                                        scheduled: None, // Retries aren't late, they're just retries
+                                        ..self.req.clone() };
+        let resp: serve::CreateRunResp = serde_json::from_str(&self.api.post("/run/create", serde_json::to_string(&req)?.as_bytes()).await?)?;
+        Ok(Job { id: resp.id, run_id: resp.run_id, req, retry: self.retry, success: self.success.clone(), redactor: self.redactor.clone(), timeout: self.timeout, kill_after: self.kill_after, tee: self.tee,
+                 cmd: self.cmd.clone(), api: self.api.clone() })
+    }
+
+    pub fn create_req(user: &str, name: &str, id: Option<&str>, cmd: &Command) -> serve::CreateRunReq {
         let mut env=vec![];
         for (k, v) in std::env::vars_os() {
             env.push((MaybeUTF8::new(k),MaybeUTF8::new(v)));
         }
-        let api = Api::new(server_url)?;
-        let resp: serve::CreateRunResp = serde_json::from_str(&api.post("/run/create", &serde_json::to_string(&serve::CreateRunReq{ user:user.to_string(), name:name.to_string(), id:id.map(|i|i.to_string()), cmd:cmd.to_string(), env:env })?.as_bytes()).await?)?;
-        Ok(Job { id:resp.id, timeout:timeout, cmd:cmd.to_owned(), api: api })
+        serve::CreateRunReq{ user:user.to_string(), name:name.to_string(), id:id.map(|i|i.to_string()), cmd:cmd.to_string(), env, start:None, attempt_of:None, scheduled:None }
     }
 
-    pub async fn run(&self) -> Result<(), Box<dyn Error>> {
+    pub async fn run(&self) -> Result<db::ExitStatus, Box<dyn Error>> {
+        let control = ControlSocket::new()?;
+        let artifacts = tempfile::Builder::new().prefix("syncron-artifacts-").tempdir()?;
+        let summary = tempfile::Builder::new().prefix("syncron-summary-").suffix(".md").tempfile()?;
+        let usage_before = child_usage(None);
         let mut child = self.cmd.create()
+            .env("SYNCRON_CONTROL", control.path())
+            .env("SYNCRON_ARTIFACTS", artifacts.path())
+            .env("SYNCRON_SUMMARY", summary.path())
             .stdin(std::process::Stdio::null())
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
@@ -43,58 +204,397 @@ impl Job {
 
         trace!("Spawned child {:?}", child);
 
-        let outpiper = Job::copy_output(self.api.clone(), child.stdout.take().unwrap(), self.id.clone(), serve::OutKind::Stdout);
-        let errpiper = Job::copy_output(self.api.clone(), child.stderr.take().unwrap(), self.id.clone(), serve::OutKind::Stderr);
-        let pipers = async { tokio::join!(outpiper, errpiper) };
-        let heartbeat = async {//|| -> Result<(), ()> {
+        let mut interrupts = Interrupts::new()?;
+        let pid = child.id();
+        let position = std::sync::Mutex::new(OutputPosition::default());
+        let tee = TeeOutput::new(self.tee)?;
+        let (frames_tx, frames_rx) = tokio::sync::mpsc::channel(64);
+        let outpiper = Job::copy_output(child.stdout.take().unwrap(), serve::OutKind::Stdout, &position, &tee, StreamMasker::new(&self.redactor), frames_tx.clone());
+        let errpiper = Job::copy_output(child.stderr.take().unwrap(), serve::OutKind::Stderr, &position, &tee, StreamMasker::new(&self.redactor), frames_tx);
+        let uploader = self.upload_output(frames_rx);
+        let pipers = async { tokio::join!(outpiper, errpiper, uploader) };
+        tokio::pin!(pipers);
+        let (control_tx, mut control_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut controller = Box::pin(async { tokio::join!(control.listen(control_tx), self.forward_control(&mut control_rx)) });
+        let heartbeat = async {
             let now = std::time::Instant::now();
             loop {
                 trace!("Waiting 1 second");
                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                 if self.timeout.is_some() && now.elapsed() > self.timeout.unwrap() {
                     trace!("Timed out");
-                    return Err(now.elapsed());
+                    return Stop::Timeout;
                 }
                 trace!("Sending Hearbeat");
-                let _resp = self.api.post(&format!("/run/{}/heartbeat", self.id), &[]).await;
+                if let Ok(resp) = self.api.post(&format!("/run/{}/heartbeat", self.id), &[]).await {
+                    // Older servers don't send anything back.
+                    if serde_json::from_str::<serve::HeartbeatResp>(&resp).map(|r| r.stop).unwrap_or(false) {
+                        trace!("Superseded");
+                        return Stop::Superseded;
+                    }
+                }
             }
-            #[allow(unreachable_code)] Ok(()) // if I remove this, it errs.
         };
-        let mut timeout = false;
-        loop {
+        tokio::pin!(heartbeat);
+        let mut stopped = None;
+        let mut interrupted = None;
+        let mut pipes_closed = false;
+        let exitcode = loop {
             tokio::select! {
-                _            = pipers    => { break; },
-                Err(elapsed) = heartbeat => {
-                    trace!("Timeout reached after {:?}! Killing child {:?}", elapsed, child);
-                    timeout = true;
-                    child.kill().await?;
-                    break;
+                _            = &mut pipers, if !pipes_closed => { pipes_closed = true },
+                exitcode     = child.wait(), if pipes_closed => { break exitcode? },
+                signal       = interrupts.forward(pid)       => { interrupted = interrupted.or(Some(signal)) },
+                _            = &mut controller               => unreachable!(),
+                stop         = &mut heartbeat                => {
+                    trace!("{:?}! Killing child {:?}", stop, child);
+                    let signal = tokio::select! {
+                        signal = terminate(&mut child, self.kill_after) => signal?,
+                        _      = self.send_heartbeats()                 => unreachable!(),
+                    };
+                    stopped = Some(match stop { Stop::Timeout    => db::ExitStatus::ClientTimeout(signal),
+                                                Stop::Superseded => db::ExitStatus::Superseded(signal) });
+                    // Give the uploader a chance to send what it has. Don't wait forever though--the child may have
+                    // left grandchildren around that are still holding the pipes open.
+                    if !pipes_closed { _ = tokio::time::timeout(std::time::Duration::from_secs(5), &mut pipers).await; }
+                    break child.wait().await?;
                 },
             }
-        }
-        let exitcode = child.wait().await?;
-        let status = match (exitcode.code(), exitcode.signal(), exitcode.core_dumped(), timeout) {
-            (_,          _,         _,     true)  => db::ExitStatus::ClientTimeout,
-            (Some(code), _,         _,     _)     => db::ExitStatus::Exited(code),
-            (_,          Some(sig), false, _)     => db::ExitStatus::Signal(sig),
-            (_,          Some(sig), true,  _)     => db::ExitStatus::CoreDump(sig),
-            (None,       None,      _,     _)     => panic!("Can't happen"),
         };
-        self.api.post(&format!("/run/{}/complete", self.id), &serde_json::to_string(&status)?.as_bytes()).await?;
-        Ok(())
+        let status = exit_status(exitcode, stopped, interrupted);
+        let usage = child_usage(usage_before.as_ref());
+        drop(controller);
+        self.send_control(std::iter::from_fn(|| control_rx.try_recv().ok()).collect()).await; // Whatever was still on its way
+        let sent = position.lock().unwrap().offset;
+        tee.finish(status, &self.success, sent).await;
+        upload_artifacts(&self.api, &self.id, artifacts.path()).await;
+        upload_summary(&self.api, &self.id, summary.path(), Some(&self.redactor)).await;
+        self.api.post_retry(&format!("/run/{}/complete?len={}", self.id, sent), &serde_json::to_string(&serve::CompleteReq { status, usage })?.as_bytes()).await?;
+        Ok(status)
+    }
+
+    // Never returns. Sending a message can take a while, and the job can say a lot in the meantime. Only the latest
+    // progress, stage and value of each metric are worth sending, so whatever piles up gets squashed down first.
+    async fn forward_control(&self, messages: &mut tokio::sync::mpsc::UnboundedReceiver<serve::Control>) {
+        loop {
+            let Some(first) = messages.recv().await else { return std::future::pending().await };
+            let batch = std::iter::once(first).chain(std::iter::from_fn(|| messages.try_recv().ok())).collect();
+            self.send_control(batch).await;
+        }
+    }
+
+    async fn send_control(&self, mut batch: Vec<serve::Control>) {
+        let mut seen = std::collections::HashSet::new();
+        batch.reverse();
+        batch.retain(|msg| seen.insert((std::mem::discriminant(msg), match msg { serve::Control::Metric { name, .. } => Some(name.clone()), _ => None })));
+        for msg in batch.into_iter().rev() {
+            let Ok(json) = serde_json::to_string(&msg) else { continue };
+            if let Err(e) = self.api.post(&format!("/run/{}/control", self.id), json.as_bytes()).await {
+                debug!("Couldn't send {:?}: {}", msg, e);
+            }
+        }
+    }
+
+    async fn send_heartbeats(&self) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            let _resp = self.api.post(&format!("/run/{}/heartbeat", self.id), &[]).await;
+        }
     }
 
-    async fn copy_output<T: tokio::io::AsyncRead+Unpin >(api: Api, mut from: T, id: String, kind: serve::OutKind)/* -> Result<(),()> */{
+    // Secrets get masked in what goes to the server, but not in what gets tee'd--that stays on this machine.
+    async fn copy_output<T: tokio::io::AsyncRead+Unpin >(mut from: T, kind: serve::OutKind, position: &std::sync::Mutex<OutputPosition>, tee: &TeeOutput, mut masker: StreamMasker<'_>, frames: tokio::sync::mpsc::Sender<serve::OutputFrame>)/* -> Result<(),()> */{
         use tokio::io::AsyncReadExt;
         let mut buffer = [0; 4096];
         loop {
-            if let Ok(read) = from.read(&mut buffer).await {
-                if read == 0 { break }
-                let _resp = api.post(&format!("/run/{}/{}", id, kind), &buffer[0..read]).await;
-            } else { break }
+            let read = from.read(&mut buffer).await.unwrap_or(0);
+            let chunk = match read {
+                0 => masker.finish(),
+                _ => { tee.write(kind, &buffer[0..read]).await; masker.push(&buffer[0..read]) },
+            };
+            if !chunk.is_empty() {
+                // Once the lock is let go the other stream can get its frame into the channel ahead of this one, so
+                // frames aren't always in offset order by the time they get uploaded.
+                let (seq, offset) = position.lock().unwrap().advance(chunk.len());
+                if frames.send(serve::OutputFrame { kind, seq, offset, chunk }).await.is_err() { break }
+            }
+            if read == 0 { break }
+        }
+    }
+
+    // Streams output to the server in one long request instead of a request per chunk. The request gets ended every
+    // so often so the server can tell us what it got--anything it didn't acknowledge gets sent again on the next one.
+    async fn upload_output(&self, mut frames: tokio::sync::mpsc::Receiver<serve::OutputFrame>) {
+        let mut unacked = std::collections::VecDeque::new();
+        let mut done = false;
+        let mut failures = 0;
+        while !done || !unacked.is_empty() {
+            if unacked.is_empty() { // Don't bother the server until there's something to send
+                match frames.recv().await {
+                    Some(frame) => unacked.push_back(frame),
+                    None        => break,
+                }
+            }
+            let (body_tx, body_rx) = tokio::sync::mpsc::channel::<Result<Vec<u8>, std::io::Error>>(16);
+            let send = async {
+                let body_tx = body_tx; // Dropping it ends the request
+                for frame in unacked.iter() {
+                    if body_tx.send(Ok(frame.to_bytes())).await.is_err() { return } // Request died. We'll find out why below.
+                }
+                let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(10);
+                let mut sent = 0;
+                while !done && sent < 1024*1024 {
+                    tokio::select! {
+                        frame = frames.recv() => match frame {
+                            Some(frame) => {
+                                sent += frame.chunk.len();
+                                let bytes = frame.to_bytes();
+                                unacked.push_back(frame);
+                                if body_tx.send(Ok(bytes)).await.is_err() { return }
+                            },
+                            None => done = true,
+                        },
+                        _ = tokio::time::sleep_until(deadline) => break,
+                    }
+                }
+            };
+            let path = format!("/run/{}/output", self.id);
+            let request = self.api.post_stream(&path, reqwest::Body::wrap_stream(tokio_stream::wrappers::ReceiverStream::new(body_rx)));
+            let ((), resp) = tokio::join!(send, request);
+            match resp.and_then(|resp| Ok(serde_json::from_str::<serve::OutputResp>(&resp)?)) {
+                Ok(resp) => {
+                    failures = 0;
+                    // Not necessarily in offset order--see copy_output()
+                    unacked.retain(|frame: &serve::OutputFrame| frame.end() > resp.received);
+                },
+                Err(e) if failures < 8 && Api::is_retryable(&e) => {
+                    let delay = std::time::Duration::from_millis(100) * 2u32.pow(failures);
+                    debug!("Output upload failed, retrying in {:?}: {}", delay, e);
+                    failures += 1;
+                    tokio::time::sleep(delay).await;
+                },
+                Err(e) => {
+                    warn!("Giving up on {} bytes of output: {}", unacked.iter().map(|frame| frame.chunk.len()).sum::<usize>(), e);
+                    unacked.clear();
+                    failures = 0;
+                },
+            }
+        }
+    }
+
+}
+
+// Every chunk of output gets the next sequence number and the offset where it goes in the log (stdout and stderr
+// share the log). The server uses them to put chunks back in order and to drop the duplicates retries cause.
+#[derive(Debug, Default)]
+struct OutputPosition {
+    seq:    u64,
+    offset: u64,
+}
+
+impl OutputPosition {
+    fn advance(&mut self, len: usize) -> (u64, u64) {
+        let here = (self.seq, self.offset);
+        self.seq += 1;
+        self.offset += len as u64;
+        here
+    }
+}
+
+enum TeeOutput {
+    Nowhere,
+    Terminal,
+    Buffer(tokio::sync::Mutex<tokio::fs::File>), // OutputFrames, so stdout and stderr can be told apart (and stay in order) later
+}
+
+impl TeeOutput {
+    fn new(tee: Tee) -> Result<TeeOutput, Box<dyn Error>> {
+        Ok(match tee {
+            Tee::Never     => TeeOutput::Nowhere,
+            Tee::Always    => TeeOutput::Terminal,
+            Tee::OnFailure => TeeOutput::Buffer(tokio::sync::Mutex::new(tokio::fs::File::from_std(tempfile::tempfile()?))),
+        })
+    }
+
+    async fn write(&self, kind: serve::OutKind, chunk: &[u8]) {
+        use tokio::io::AsyncWriteExt;
+        match self {
+            TeeOutput::Nowhere  => {},
+            TeeOutput::Terminal => echo(kind, chunk).await,
+            TeeOutput::Buffer(file) => {
+                let frame = serve::OutputFrame { kind, seq: 0, offset: 0, chunk: chunk.to_vec() };
+                if let Err(e) = file.lock().await.write_all(&frame.to_bytes()).await {
+                    warn!("Couldn't save {} for --tee=on-failure: {}", kind, e);
+                }
+            },
+        }
+    }
+
+    // Whether the job failed is up to its success policy, the same as on the server. Warnings don't count.
+    async fn finish(&self, status: db::ExitStatus, success: &db::SuccessPolicy, log_len: u64) {
+        use tokio::io::AsyncSeekExt;
+        let TeeOutput::Buffer(file) = self else { return };
+        if success.outcome(status, log_len) != db::Outcome::Failure { return }
+        let mut file = file.lock().await;
+        if let Err(e) = file.rewind().await {
+            warn!("Couldn't replay output for --tee=on-failure: {}", e);
+            return;
+        }
+        let mut file = tokio::io::BufReader::new(&mut *file);
+        loop {
+            let frame = match serve::OutputFrame::read(&mut file).await {
+                Ok(Some(frame)) => frame,
+                Ok(None)        => break,
+                Err(e)          => { warn!("Couldn't replay output for --tee=on-failure: {}", e); break },
+            };
+            echo(frame.kind, &frame.chunk).await;
+        }
+    }
+}
+
+// Copy output to the matching one of our own stdout/stderr. There's nobody to tell if it fails.
+async fn echo(kind: serve::OutKind, chunk: &[u8]) {
+    use tokio::io::AsyncWriteExt;
+    match kind {
+        serve::OutKind::Stdout => { let mut out = tokio::io::stdout(); _ = out.write_all(chunk).await; _ = out.flush().await; },
+        serve::OutKind::Stderr => { let mut out = tokio::io::stderr(); _ = out.write_all(chunk).await; _ = out.flush().await; },
+    }
+}
+
+// The child gets its own process group (see Command::create()) so this gets everything it started, too. They all get
+// a SIGTERM so they can clean up after themselves, then whatever's left after `kill_after` gets a SIGKILL. Returns
+// the signal that finally did the job in.
+async fn terminate(child: &mut tokio::process::Child, kill_after: std::time::Duration) -> Result<i32, Box<dyn Error>> {
+    use nix::sys::signal::{killpg, Signal};
+    let Some(pid) = child.id() else { return Ok(Signal::SIGTERM as i32) }; // Already reaped--it beat us to it
+    let pgid = nix::unistd::Pid::from_raw(pid as i32);
+    killpg(pgid, Signal::SIGTERM).map_err(|e| crate::wrap(e, &format!("kill -TERM -{}", pgid)))?;
+    let signal = match tokio::time::timeout(kill_after, child.wait()).await {
+        Ok(_)  => Signal::SIGTERM,
+        Err(_) => { trace!("Child {} still running {:?} after SIGTERM", pid, kill_after); Signal::SIGKILL },
+    };
+    // Even if the child went quietly, anything it left behind in the group doesn't get to stay.
+    match killpg(pgid, Signal::SIGKILL) {
+        Ok(()) | Err(nix::errno::Errno::ESRCH) => {},
+        Err(e) => Err(crate::wrap(e, &format!("kill -KILL -{}", pgid)))?,
+    }
+    Ok(signal as i32)
+}
+
+// Signals that mean someone wants us to stop. Instead of dying (and leaving the run hanging until the server gives
+// up on it) we pass them on to the job's process group and let it decide what to do. We complete the run when it exits.
+struct Interrupts {
+    term: tokio::signal::unix::Signal,
+    int:  tokio::signal::unix::Signal,
+    hup:  tokio::signal::unix::Signal,
+}
+
+impl Interrupts {
+    fn new() -> Result<Interrupts, Box<dyn Error>> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Interrupts { term: signal(SignalKind::terminate())?,
+                        int:  signal(SignalKind::interrupt())?,
+                        hup:  signal(SignalKind::hangup())? })
+    }
+
+    // Waits for one of the signals and passes it on. Returns the signal.
+    async fn forward(&mut self, pid: Option<u32>) -> i32 {
+        use nix::sys::signal::Signal;
+        let signal = tokio::select! {
+            _ = self.term.recv() => Signal::SIGTERM,
+            _ = self.int.recv()  => Signal::SIGINT,
+            _ = self.hup.recv()  => Signal::SIGHUP,
+        };
+        if let Some(pid) = pid {
+            info!("Got {}, passing it on to the job", signal);
+            if let Err(e) = nix::sys::signal::killpg(nix::unistd::Pid::from_raw(pid as i32), signal) {
+                warn!("Couldn't pass {} on to the job: {}", signal, e);
+            }
         }
+        signal as i32
+    }
+}
+
+// How long to wait before starting, somewhere from 0 to `max`. With `stable` (the job's id or name) it's the same
+// every time for this host and job, so a fleet still spreads out but each host runs at a predictable time.
+pub fn splay(max: std::time::Duration, stable: Option<&str>) -> Result<std::time::Duration, Box<dyn Error>> {
+    use rand::Rng;
+    use sha2::Digest;
+    let max_ms = max.as_millis() as u64;
+    let ms = match stable {
+        None      => rand::thread_rng().gen_range(0..=max_ms),
+        Some(job) => {
+            let mut buf = [0u8; 256];
+            let host = nix::unistd::gethostname(&mut buf).map_err(|e| crate::wrap(e, "gethostname"))?;
+            let hash = sha2::Sha256::new().chain_update(host.to_bytes()).chain_update(b"\0").chain_update(job.as_bytes()).finalize();
+            u64::from_be_bytes(hash[0..8].try_into()?) % (max_ms + 1)
+        },
+    };
+    Ok(std::time::Duration::from_millis(ms))
+}
+
+// Whether it's worth trying the job again. Someone stopping it on purpose isn't the kind of failure a retry fixes,
+// and neither is something the job's success policy says isn't a failure. We don't know what made it to the server's
+// log, so empty output doesn't get to count.
+pub fn retryable(status: db::ExitStatus, policy: &db::SuccessPolicy) -> bool {
+    match status {
+        db::ExitStatus::Interrupted(_)    |
+        db::ExitStatus::Superseded(_)     |
+        db::ExitStatus::Skipped           => false,
+        _                                 => policy.outcome(status, u64::MAX) == db::Outcome::Failure,
+    }
+}
+
+// Sleeps between attempts. If we get a TERM, INT or HUP in the meantime it returns the signal instead.
+pub async fn retry_delay(delay: std::time::Duration) -> Result<Option<i32>, Box<dyn Error>> {
+    let mut interrupts = Interrupts::new()?;
+    Ok(tokio::select! {
+        _      = tokio::time::sleep(delay)  => None,
+        signal = interrupts.forward(None)   => Some(signal),
+    })
+}
+
+// Why the heartbeat loop gave up on the job
+#[derive(Debug)]
+enum Stop {
+    Timeout,
+    Superseded, // The server says a newer run wants this one gone
+}
+
+// `stopped` is how it went if we had to kill the job ourselves (timeout or superseded).
+fn exit_status(exitcode: std::process::ExitStatus, stopped: Option<db::ExitStatus>, interrupted: Option<i32>) -> db::ExitStatus {
+    match (exitcode.code(), exitcode.signal(), exitcode.core_dumped(), stopped, interrupted) {
+        (_,          _,         _,     Some(status), _)      => status,
+        (_,          _,         _,     _,         Some(sig)) => db::ExitStatus::Interrupted(sig),
+        (Some(code), _,         _,     _,         _)         => db::ExitStatus::Exited(code),
+        (_,          Some(sig), false, _,         _)         => db::ExitStatus::Signal(sig),
+        (_,          Some(sig), true,  _,         _)         => db::ExitStatus::CoreDump(sig),
+        (None,       None,      _,     _,         _)         => panic!("Can't happen"),
     }
+}
 
+// The totals for all our (waited for) children. Retries mean there can be more than one, so pass in what it said before
+// the child started to get just that child's share. Except max_rss: that's the most any one of them used, so it can't be
+// taken apart.
+fn child_usage(before: Option<&db::Usage>) -> Option<db::Usage> {
+    use nix::libc;
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, usage.as_mut_ptr()) } != 0 {
+        warn!("getrusage failed: {}", std::io::Error::last_os_error());
+        return None;
+    }
+    let usage = unsafe { usage.assume_init() };
+    let ms = |tv: libc::timeval| tv.tv_sec as u64 * 1000 + tv.tv_usec as u64 / 1000;
+    let zero = db::Usage::default();
+    let before = before.unwrap_or(&zero);
+    Some(db::Usage {
+        user_ms:              ms(usage.ru_utime).saturating_sub(before.user_ms),
+        system_ms:            ms(usage.ru_stime).saturating_sub(before.system_ms),
+        max_rss:              usage.ru_maxrss as u64 * if cfg!(target_os = "macos") { 1 } else { 1024 }, // Linux (and the BSDs) say kilobytes
+        block_in:             (usage.ru_inblock as u64).saturating_sub(before.block_in),
+        block_out:            (usage.ru_oublock as u64).saturating_sub(before.block_out),
+        voluntary_switches:   (usage.ru_nvcsw as u64).saturating_sub(before.voluntary_switches),
+        involuntary_switches: (usage.ru_nivcsw as u64).saturating_sub(before.involuntary_switches),
+    })
 }
 
 #[derive(Debug,Clone)]
@@ -103,10 +603,28 @@ pub struct Api {
     ua:     reqwest::Client,
 }
 
+// $SYNCRON_TOKEN, or else what's in ~/.syncron/token (see `syncron token create`). Without one the server has to not
+// be requiring them.
+fn api_token() -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(token) = std::env::var("SYNCRON_TOKEN").ok().filter(|t| !t.is_empty()) { return Ok(Some(token)) }
+    let Some(home) = std::env::var_os("HOME") else { return Ok(None) };
+    let path = std::path::PathBuf::from(home).join(".syncron").join("token");
+    match std::fs::read_to_string(&path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(crate::wrap(e, &format!("read {}", path.to_string_lossy())))?,
+        Ok(token) => Ok(Some(token.trim().to_string()).filter(|t| !t.is_empty())),
+    }
+}
+
 impl Api {
-    fn new(server_url: Url) -> Result<Api, Box<dyn Error>> {
+    pub fn new(server_url: Url) -> Result<Api, Box<dyn Error>> {
         let mut fake_browser_headers = header::HeaderMap::new();
         fake_browser_headers.insert(ACCEPT, header::HeaderValue::from_static("application/json"));
+        if let Some(token) = api_token()? {
+            let mut auth = header::HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|e| crate::wrap(e, "Bad API token"))?;
+            auth.set_sensitive(true);
+            fake_browser_headers.insert(AUTHORIZATION, auth);
+        }
         let client = reqwest::Client::builder()
             .connection_verbose(true)
             .default_headers(fake_browser_headers)
@@ -132,6 +650,36 @@ impl Api {
         Ok(resp.text().await?)
     }
 
+    // For things we really don't want to lose. Retries with exponential backoff (about 25 seconds all told), but only
+    // if the server couldn't be reached or had an internal error--a 4xx isn't going to get any better.
+    pub async fn post_retry(&self, path: &str, body: &[u8]) -> anyhow::Result<String> {
+        let mut delay = std::time::Duration::from_millis(100);
+        for _ in 0..8 {
+            match self.post(path, body).await {
+                Err(e) if Self::is_retryable(&e) => {
+                    debug!("POST {} failed, retrying in {:?}: {}", path, delay, e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                },
+                result => return result,
+            }
+        }
+        self.post(path, body).await
+    }
+
+    pub async fn post_stream(&self, path: &str, body: reqwest::Body) -> anyhow::Result<String> {
+        let resp = self.ua.post(self.server.join(path)?)
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(body)
+            .send()
+            .await?;
+
+        resp.error_for_status_ref()?;
+        let resp_str = resp.text().await?;
+        trace!("API: {} -> {}", self.server.join(path)?, resp_str);
+        Ok(resp_str)
+    }
+
     #[allow(dead_code)]
     pub async fn get(&self, path: &str) -> anyhow::Result<String> {
         let resp = self.ua.get(self.server.join(path)?)
@@ -174,6 +722,22 @@ impl Api {
         }
     }
 
+    fn is_retryable(err: &anyhow::Error) -> bool {
+        match err.downcast_ref::<reqwest::Error>() {
+            Some(e) => e.status().map(|s| s.is_server_error()).unwrap_or(true),
+            None => false,
+        }
+    }
+
+    // The server understood the request and said no (a 4xx). Asking again won't change its mind. Except when the
+    // problem is us (a bad or missing token) or the timing (timeouts, rate limits)--those are worth trying again later.
+    pub fn is_rejection(err: &anyhow::Error) -> bool {
+        use reqwest::StatusCode;
+        err.downcast_ref::<reqwest::Error>().and_then(|e| e.status()).is_some_and(|s| {
+            s.is_client_error() && ![StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN, StatusCode::REQUEST_TIMEOUT, StatusCode::TOO_MANY_REQUESTS].contains(&s)
+        })
+    }
+
     pub fn is_404(err: &anyhow::Error) -> bool {
         match err.downcast_ref::<reqwest::Error>() {
             Some(e) => e.status() == Some(reqwest::StatusCode::NOT_FOUND),
@@ -182,14 +746,36 @@ impl Api {
     }
 }
 
-pub async fn fallback_run(timeout: Option<std::time::Duration>, cmd: &Command)  -> Result<(), Box<dyn Error>> {
+// If we have a spool then the output goes to our stdout/stderr and also gets written to the spool so it can be
+// uploaded when the server comes back. Without one we just get out of the way and let the output pass through.
+pub async fn fallback_run(timeout: Option<std::time::Duration>, kill_after: std::time::Duration, cmd: &Command, spool: Option<&SpoolRun>)  -> Result<db::ExitStatus, Box<dyn Error>> {
     // This is largely a copy+paste of Job::run(), above, but I don't know that it's worth it to abstract and de-duplicate.
+    let stdio = || if spool.is_some() { std::process::Stdio::piped() } else { std::process::Stdio::inherit() };
+    // Artifacts and the summary get spooled along with everything else. Without a spool they have nowhere to go, but
+    // the job still gets somewhere to put them so it doesn't trip over a missing $SYNCRON_ARTIFACTS or $SYNCRON_SUMMARY.
+    let scratch = tempfile::Builder::new().prefix("syncron-artifacts-").tempdir()?;
+    let artifacts = spool.map(|s| s.artifacts_path()).unwrap_or_else(|| scratch.path().to_path_buf());
+    let summary = spool.map(|s| s.summary_path()).unwrap_or_else(|| scratch.path().join("summary.md"));
+    let usage_before = child_usage(None);
     let mut child = cmd.create()
+        .env("SYNCRON_ARTIFACTS", &artifacts)
+        .env("SYNCRON_SUMMARY", &summary)
         .stdin(std::process::Stdio::null())
+        .stdout(stdio())
+        .stderr(stdio())
         .spawn()?;
 
     trace!("Spawned child in fallback mode{:?}", child);
 
+    let mut interrupts = Interrupts::new()?;
+    let pid = child.id();
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let pipers = async {
+        if let (Some(spool), Some(stdout), Some(stderr)) = (spool, stdout, stderr) {
+            tokio::join!(copy_output_to_spool(stdout, tokio::io::stdout(), spool, serve::OutKind::Stdout),
+                         copy_output_to_spool(stderr, tokio::io::stderr(), spool, serve::OutKind::Stderr));
+        }
+    };
     let heartbeat = async {
         let now = std::time::Instant::now();
         loop {
@@ -202,32 +788,50 @@ pub async fn fallback_run(timeout: Option<std::time::Duration>, cmd: &Command)
         }
         #[allow(unreachable_code)] Ok(()) // if I remove this, it errs.
     };
-    loop {
+    tokio::pin!(pipers, heartbeat);
+    let mut timed_out = None;
+    let mut interrupted = None;
+    let mut pipes_closed = false;
+    let status = loop {
         tokio::select! {
-            status     = child.wait()    => {
-                match status {
-                    Err(e) => {
-                        error!("Child process failed: {}", e);
-                        Err(e)?;
-                        break;
-                    },
-                    Ok(status) => {
-                        trace!("Child exited with {}", status);
-                        if let Some(code) = status.code() {
-                            std::process::exit(code); // pass it on
-                        }
-                        break;
-                    }
-                }
-            },
-            Err(elapsed) = heartbeat => {
+            _            = &mut pipers, if !pipes_closed => { pipes_closed = true },
+            status       = child.wait(), if pipes_closed => { break status },
+            signal       = interrupts.forward(pid)       => { interrupted = interrupted.or(Some(signal)) },
+            Err(elapsed) = &mut heartbeat                => {
                 error!("Timeout reached after {:?}! Killing child {:?}", elapsed, child);
-                child.kill().await?;
-                break;
+                timed_out = Some(db::ExitStatus::ClientTimeout(terminate(&mut child, kill_after).await?));
+                break child.wait().await
             },
         }
+    };
+    let status = match status {
+        Err(e) => {
+            error!("Child process failed: {}", e);
+            Err(e)?
+        },
+        Ok(status) => status,
+    };
+    trace!("Child exited with {}", status);
+    let status = exit_status(status, timed_out, interrupted);
+    if let Some(spool) = spool {
+        if let Err(e) = spool.complete(status, child_usage(usage_before.as_ref())) {
+            error!("Couldn't write status to spool: {}", e);
+        }
+    }
+    Ok(status)
+}
+
+async fn copy_output_to_spool<R: tokio::io::AsyncRead+Unpin, W: tokio::io::AsyncWrite+Unpin>(mut from: R, mut to: W, spool: &SpoolRun, kind: serve::OutKind) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let mut buffer = [0; 4096];
+    while let Ok(read) = from.read(&mut buffer).await {
+        if read == 0 { break }
+        _ = to.write_all(&buffer[0..read]).await;
+        _ = to.flush().await;
+        if let Err(e) = spool.write(kind, &buffer[0..read]).await {
+            warn!("Couldn't write {} to spool: {}", kind, e);
+        }
     }
-    Ok(())
 }
 
 #[derive(Clone, Debug)]
@@ -252,6 +856,8 @@ impl Command {
         };
         let mut c = tokio::process::Command::new(exe);
         c.args(args);
+        c.process_group(0); // So we can kill off everything the job starts if it times out
+        c.env_remove("SYNCRON_TOKEN"); // The job has no business with our API token
         c
     }
 
@@ -331,7 +937,7 @@ mod tests {
             (MaybeUTF8::new(OsString::from("HOME")),            MaybeUTF8::new(OsString::from("/home/my-home-dir"))),
             (MaybeUTF8::new(OsString::from("SOMETHING WACKY")), MaybeUTF8::new(OsString::from("Oh\nDear"))),
         ];
-        let run = db::Run::create(&db, "test-user", "David's The _absolute_ Greatest", None, cmd.to_string(), env).await.expect("db::Run create worked");
+        let run = db::Run::create(&db, "test-user", "David's The _absolute_ Greatest", None, cmd.to_string(), env, None).await.expect("db::Run create worked");
         assert_eq!(run.job.id, "david-s-the-absolute-greatest");
 
         let id = run.client_id.expect("got client_id");
@@ -339,9 +945,9 @@ mod tests {
         assert!(run.date - run2.date < chrono::Duration::milliseconds(1000), "Dates are less close than expected {} vs {}", run.date, run2.date);
         run2.date = run.date;
         assert_eq!(run, run2);
-        run2.add_stdout("Some text. ").await.expect("text added");
-        run2.add_stdout("Some more text.\n").await.expect("more text added");
-        run2.add_stdout("Even more text.\n").await.expect("even more text added");
+        run2.add_stdout(serve::OutKind::Stdout, b"Some text. ").await.expect("text added");
+        run2.add_stdout(serve::OutKind::Stdout, b"Some more text.\n").await.expect("more text added");
+        run2.add_stdout(serve::OutKind::Stdout, b"Even more text.\n").await.expect("even more text added");
         run2.complete(db::ExitStatus::Exited(0)).await.expect("completed with no errors");
 
         assert_file_eq!(&db_path.path().join("jobs").join("test-user").join("david-s-the-absolute-greatest")
@@ -352,17 +958,334 @@ mod tests {
         assert!(run3.is_err(), "db::Run::from_client_id() returned was {:?}", run3);
     }
 
+    #[tokio::test]
+    async fn out_of_order() {
+        let (db, _db_path) = test_db().await;
+        let run = db::Run::create(&db, "test-user", "Out of Order", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        use serve::OutKind::{Stdout, Stderr};
+        run.add_output(Stderr, b"Second. ", Some(1), 7).await.expect("early chunk parked");
+        assert_eq!(run.log_len(), 0);
+        run.add_output(Stdout, b"First. ",  Some(0), 0).await.expect("first chunk added");
+        run.add_output(Stdout, b"First. ",  Some(0), 0).await.expect("duplicate chunk ignored");
+        run.add_output(Stdout, b"Fifth.",   Some(4), 28).await.expect("chunk after a gap parked");
+        run.add_output(Stdout, b"Third. ",  Some(2), 15).await.expect("third chunk added");
+        assert_file_eq!(&run.log_path(), "First. Second. Third. ");
+        assert_eq!(run.read_stream(Stdout, None, None).await.expect("read stdout"), (14, b"First. Third. ".to_vec()));
+        assert_eq!(run.read_stream(Stderr, None, None).await.expect("read stderr"), (8, b"Second. ".to_vec()));
+        assert_eq!(run.read_stream(Stdout, Some(3), Some(6)).await.expect("read stdout slice"), (14, b"st. Th".to_vec()));
+        run.complete_at(db::ExitStatus::Exited(0), chrono::Local::now().timestamp_millis(), Some(40), None).await.expect("completed with no errors");
+
+        assert_file_eq!(&run.log_path(), "First. Second. Third. Fifth.");
+        assert!(!run.log_path().with_file_name("pending").exists(), "pending dir should be cleaned up");
+        assert_eq!(run.get_info().await.expect("got info").log_gaps, vec![db::LogGap { at: 22, missing: 6 }, db::LogGap { at: 28, missing: 6 }]);
+    }
+
+    #[tokio::test]
+    async fn binary_output() {
+        let (db, _db_path) = test_db().await;
+        let run = db::Run::create(&db, "test-user", "Binary", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        let mut events = db.broker().subscribe(&[&format!("job/test-user/{}/run/{}/log", run.job.id, run.run_id)]).await.expect("subscribed");
+        // "é" split across chunks, then some Latin-1.
+        run.add_output(serve::OutKind::Stdout, b"caf\xc3", Some(0), 0).await.expect("first half added");
+        run.add_output(serve::OutKind::Stdout, b"\xa9 na\xefve\n", Some(1), 4).await.expect("second half added");
+        assert_eq!(std::fs::read(run.log_path()).expect("read log"), b"caf\xc3\xa9 na\xefve\n");
+        let mut text = String::new();
+        for _ in 0..2 {
+            let event = serde_json::to_value(events.recv().await.expect("got event")).expect("event serialized");
+            text += event["run_log_append"]["chunk"].as_str().expect("got chunk");
+        }
+        assert_eq!(text, "café na\u{fffd}ve\n");
+    }
+
     #[tokio::test]
     async fn heartbeat_timeout() {
         let (db, _db_path) = test_db().await;
         let cmd = "echo a simple test";
-        let run = db::Run::create(&db, "test-user", "David's The _absolute_ Greatest", None, cmd.to_string(), vec![]).await.expect("db::Run create worked");
+        let run = db::Run::create(&db, "test-user", "David's The _absolute_ Greatest", None, cmd.to_string(), vec![], None).await.expect("db::Run create worked");
 
         sqlx::query!("UPDATE run SET heartbeat = 0 WHERE run_id = ?", run.run_db_id).execute(db.sql()).await.expect("update run set heartbeat");
         let info = run.info().await.expect("got info");
         assert_eq!(info.status, Some(db::ExitStatus::ServerTimeout));
     }
 
+    #[tokio::test]
+    async fn overlap() {
+        let (db, _db_path) = test_db().await;
+        let start = || db::Run::start(&db, "test-user", "Overlapping", None, "sleep 100".to_string(), vec![]);
+        let set_overlap = |job: db::Job, overlap| async move { job.update_settings(&db::JobSettings { overlap, ..Default::default() }).await.expect("settings updated") };
+        let db::Start::Run(first) = start().await.expect("first started") else { panic!("nothing else is running") };
+
+        set_overlap(first.job.clone(), db::Overlap::Skip).await;
+        let db::Start::Skip(skipped) = start().await.expect("skip started") else { panic!("should skip") };
+        assert_eq!(skipped.info().await.expect("got info").status, Some(db::ExitStatus::Skipped));
+
+        set_overlap(first.job.clone(), db::Overlap::Queue).await;
+        assert!(matches!(start().await.expect("queue started"), db::Start::Wait(_)));
+        assert!(!first.superseded().await.expect("got superseded"));
+
+        set_overlap(first.job.clone(), db::Overlap::KillPrevious).await;
+        assert!(matches!(start().await.expect("kill previous started"), db::Start::Wait(_)));
+        assert!(first.superseded().await.expect("got superseded"));
+        first.complete(db::ExitStatus::Superseded(15)).await.expect("completed");
+        let db::Start::Run(second) = start().await.expect("second started") else { panic!("previous run is done") };
+
+        set_overlap(second.job.clone(), db::Overlap::Allow).await;
+        assert!(matches!(start().await.expect("allow started"), db::Start::Run(_)));
+    }
+
+    #[tokio::test]
+    async fn retries() {
+        let (db, _db_path) = test_db().await;
+        let first = db::Run::create(&db, "test-user", "Flaky", None, "false".to_string(), vec![], None).await.expect("db::Run create worked");
+        assert_eq!(first.info().await.expect("got info").attempt, None);
+        first.complete(db::ExitStatus::Signal(9)).await.expect("first completed");
+        assert_eq!(first.job.successes(None, None).await.expect("got successes"), vec![(first.date.timestamp_millis(), Some(db::Outcome::Failure))]);
+
+        let second = first.retry("false".to_string(), vec![], None).await.expect("second attempt created");
+        assert_eq!(second.info().await.expect("got info").attempt, Some(db::Attempt { number: 2, of: first.run_id.clone() }));
+        assert_eq!(first.job.successes(None, None).await.expect("got successes"), vec![(first.date.timestamp_millis(), None)]);
+        second.complete(db::ExitStatus::Signal(9)).await.expect("second completed");
+        assert_eq!(first.job.successes(None, None).await.expect("got successes"), vec![(first.date.timestamp_millis(), Some(db::Outcome::Failure))]);
+
+        let third = first.retry("false".to_string(), vec![], None).await.expect("third attempt created");
+        assert_eq!(third.info().await.expect("got info").attempt, Some(db::Attempt { number: 3, of: first.run_id.clone() }));
+        third.complete(db::ExitStatus::Exited(0)).await.expect("third completed");
+        assert_eq!(first.job.successes(None, None).await.expect("got successes"), vec![(first.date.timestamp_millis(), Some(db::Outcome::Success))]);
+
+        // Pruned out from under the retries. They still count, under the oldest one that's left.
+        first.delete("pruned").await.expect("first deleted");
+        assert_eq!(second.job.successes(None, None).await.expect("got successes"), vec![(second.date.timestamp_millis(), Some(db::Outcome::Success))]);
+    }
+
+    #[tokio::test]
+    async fn success_policy() {
+        let (db, _db_path) = test_db().await;
+        let first = db::Run::create(&db, "test-user", "Picky", None, "nothing-to-do".to_string(), vec![], None).await.expect("db::Run create worked");
+        first.add_stdout(serve::OutKind::Stdout, b"Nothing to do\n").await.expect("added output");
+        first.complete(db::ExitStatus::Exited(1)).await.expect("first completed");
+        assert_eq!(first.job.successes(None, None).await.expect("got successes"), vec![(first.date.timestamp_millis(), Some(db::Outcome::Failure))]);
+
+        let success = db::SuccessPolicy { exit_codes: vec![0, 1], timeout_fails: false, ..Default::default() };
+        first.job.update_settings(&db::JobSettings { success, ..Default::default() }).await.expect("settings updated");
+        let second = db::Run::create(&db, "test-user", "Picky", None, "sleep 100".to_string(), vec![], None).await.expect("db::Run create worked");
+        second.complete(db::ExitStatus::ClientTimeout(15)).await.expect("second completed");
+        assert_eq!(second.job.successes(None, None).await.expect("got successes"), vec![(first.date.timestamp_millis(), Some(db::Outcome::Failure)), (second.date.timestamp_millis(), Some(db::Outcome::Success))]);
+
+        assert_eq!(second.job.reevaluate_successes().await.expect("re-evaluated"), 1);
+        assert_eq!(second.job.successes(None, None).await.expect("got successes"), vec![(first.date.timestamp_millis(), Some(db::Outcome::Success)), (second.date.timestamp_millis(), Some(db::Outcome::Success))]);
+        assert_eq!(second.job.reevaluate_successes().await.expect("re-evaluated again"), 0);
+
+        let warning = db::SuccessPolicy { warning_codes: vec![2], ..Default::default() };
+        second.job.update_settings(&db::JobSettings { success: warning, ..Default::default() }).await.expect("settings updated");
+        let third = db::Run::create(&db, "test-user", "Picky", None, "partly-works".to_string(), vec![], None).await.expect("db::Run create worked");
+        third.add_stdout(serve::OutKind::Stdout, b"Skipped 3 files\n").await.expect("added output");
+        third.complete(db::ExitStatus::Exited(2)).await.expect("third completed");
+        assert_eq!(third.info().await.expect("got info").outcome, Some(db::Outcome::Warning));
+        assert!(!retryable(db::ExitStatus::Exited(2), &third.job.settings.success));
+    }
+
+    #[tokio::test]
+    async fn log_rules() {
+        let (db, _db_path) = test_db().await;
+        let job = db::Job::ensure(&db, "test-user", "Legacy", None).await.expect("job created");
+        let log_rules = vec![db::LogRule { pattern: "(?i)warning".into(),  level: db::LogLevel::Warning },
+                             db::LogRule { pattern: "^Traceback".into(),   level: db::LogLevel::Failure },
+                             db::LogRule { pattern: "WARNING: fatal".into(), level: db::LogLevel::Failure }];
+        job.update_settings(&db::JobSettings { log_rules, ..Default::default() }).await.expect("settings updated");
+        assert!(job.update_settings(&db::JobSettings { log_rules: vec![db::LogRule { pattern: "(".into(), level: db::LogLevel::Warning }], ..Default::default() }).await.is_err());
+
+        let run = db::Run::create(&db, "test-user", "Legacy", None, "legacy.py".to_string(), vec![], None).await.expect("db::Run create worked");
+        run.add_stdout(serve::OutKind::Stdout, b"starting\nwarning: disk is low\n").await.expect("added output");
+        run.add_stdout(serve::OutKind::Stderr, b"Traceback (most recent call last):\n  oops\nWARNING: fatal").await.expect("added output");
+        run.complete(db::ExitStatus::Exited(0)).await.expect("completed");
+        assert_eq!(run.info().await.expect("got info").log_matches,
+                   vec![db::LogMatch { line: 2, offset: 9,  level: db::LogLevel::Warning, text: "warning: disk is low".into() },
+                        db::LogMatch { line: 3, offset: 30, level: db::LogLevel::Failure, text: "Traceback (most recent call last):".into() },
+                        db::LogMatch { line: 5, offset: 72, level: db::LogLevel::Failure, text: "WARNING: fatal".into() }]);
+        assert_eq!(run.job.successes(None, None).await.expect("got successes"), vec![(run.date.timestamp_millis(), Some(db::Outcome::Failure))]);
+
+        job.update_settings(&db::JobSettings::default()).await.expect("settings cleared");
+        let job = db::Job::new(&db, "test-user", &job.id).await.expect("job loaded").expect("job exists");
+        assert_eq!(job.reevaluate_successes().await.expect("re-evaluated"), 1);
+        assert_eq!(run.info().await.expect("got info").log_matches, vec![]);
+        assert_eq!(job.successes(None, None).await.expect("got successes"), vec![(run.date.timestamp_millis(), Some(db::Outcome::Success))]);
+    }
+
+    #[tokio::test]
+    async fn reported_progress() {
+        let (db, _db_path) = test_db().await;
+        let run = db::Run::create(&db, "test-user", "Chatty", None, "backup".to_string(), vec![], None).await.expect("db::Run create worked");
+        assert_eq!(run.progress().await.expect("got progress").map(|p| (p.percent, p.stage)), None);
+
+        run.set_progress_report(&db::ProgressReport { stage: Some("Uploading".into()), ..Default::default() }).await.expect("stage reported");
+        assert_eq!(run.progress().await.expect("got progress").map(|p| (p.percent, p.stage)), Some((None, Some("Uploading".into()))));
+
+        run.set_progress_report(&db::ProgressReport { done: Some(25.0), total: Some(100.0), stage: Some("Uploading".into()) }).await.expect("progress reported");
+        let progress = run.progress().await.expect("got progress").expect("has progress");
+        assert_eq!((progress.percent, progress.stage), (Some(0.25), Some("Uploading".into())));
+        assert!(progress.eta_seconds.is_some());
+    }
+
+    #[tokio::test]
+    async fn metrics() {
+        let (db, _db_path) = test_db().await;
+        let first = db::Run::create(&db, "test-user", "Backup", None, "backup".to_string(), vec![], None).await.expect("db::Run create worked");
+        first.set_metric("bytes", 1000.0).await.expect("metric set");
+        first.set_metric("files", 10.0).await.expect("metric set");
+        first.set_metric("bytes", 2000.0).await.expect("metric set again");
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await; // Runs need different start times
+        let second = db::Run::create(&db, "test-user", "Backup", None, "backup".to_string(), vec![], None).await.expect("db::Run create worked");
+        second.set_metric("files", 1.0).await.expect("metric set");
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let _third = db::Run::create(&db, "test-user", "Backup", None, "backup".to_string(), vec![], None).await.expect("db::Run create worked");
+
+        assert_eq!(first.info().await.expect("info").metrics, db::Metrics::from([("bytes".to_string(), 2000.0), ("files".to_string(), 10.0)]));
+        let series = first.job.metrics(None, None, None).await.expect("metrics");
+        assert_eq!(series.iter().map(|(start, _)| *start).collect::<Vec<_>>(), vec![first.date.timestamp_millis(), second.date.timestamp_millis()]);
+        assert_eq!(series.into_iter().map(|(_, m)| m.len()).collect::<Vec<_>>(), vec![2, 1]);
+        let bytes = first.job.metrics(Some("bytes"), None, None).await.expect("bytes metric");
+        assert_eq!(bytes, vec![(first.date.timestamp_millis(), db::Metrics::from([("bytes".to_string(), 2000.0)]))]);
+    }
+
+    #[tokio::test]
+    async fn artifacts_and_summary() {
+        let (db, _db_path) = test_db().await;
+        let run = db::Run::create(&db, "test-user", "Reporter", None, "report".to_string(), vec![], None).await.expect("db::Run create worked");
+        run.add_output(serve::OutKind::Stdout, b"hi\n", None, 0).await.expect("output added");
+        for (name, contents) in [("report.html", "<html/>"), ("shots/home.png", "PNG")] {
+            tokio::fs::write(run.new_artifact_path(std::path::Path::new(name)).await.expect("artifact path"), contents).await.expect("artifact written");
+        }
+        assert!(run.new_artifact_path(std::path::Path::new("../escape")).await.is_err());
+        assert!(run.artifact_path(std::path::Path::new("../log")).is_none());
+        assert_eq!(run.artifacts().expect("artifacts"), vec![db::Artifact { name: "report.html".into(), size: 7 }, db::Artifact { name: "shots/home.png".into(), size: 3 }]);
+        assert_eq!(run.size(), 3 + 7 + 3);
+        assert_eq!(run.summary().await.expect("no summary yet"), None);
+        run.set_summary("# Nightly report\n").await.expect("summary set");
+        assert_eq!(run.summary().await.expect("summary"), Some("# Nightly report\n".to_string()));
+        assert_eq!(run.size(), 3 + 7 + 3 + 17);
+
+        let artifacts_dir = run.log_path().with_file_name("artifacts");
+        run.delete("testing").await.expect("deleted");
+        assert!(!artifacts_dir.exists());
+        assert!(!run.log_path().with_file_name("summary.md").exists());
+    }
+
+    #[tokio::test]
+    async fn delete_job() {
+        let (db, db_path) = test_db().await;
+        let run = db::Run::create(&db, "test-user", "Doomed", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        run.add_output(serve::OutKind::Stdout, b"hi\n", None, 0).await.expect("output added");
+        run.complete(db::ExitStatus::Exited(0)).await.expect("completed");
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await; // Runs can't start in the same ms
+        let unfinished = db::Run::create(&db, "test-user", "Doomed", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        unfinished.add_output(serve::OutKind::Stdout, b"still going\n", None, 0).await.expect("output added");
+        let job = run.job.clone();
+        let job_dir = db_path.path().join(job.job_path());
+        assert!(job_dir.exists());
+
+        job.delete().await.expect("deleted");
+        assert!(db::Job::new(&db, "test-user", "doomed").await.expect("job lookup").is_none());
+        assert!(db::Job::jobs(&db).await.expect("jobs").is_empty());
+        db::Job::deleted(&db, "test-user", "doomed").await.expect("job lookup").expect("still there").undelete().await.expect("undeleted");
+        assert!(db::Job::new(&db, "test-user", "doomed").await.expect("job lookup").is_some());
+
+        // Running again brings it back, too
+        job.delete().await.expect("deleted");
+        db::Job::ensure(&db, "test-user", "Doomed", None).await.expect("ensured");
+        assert!(db::Job::new(&db, "test-user", "doomed").await.expect("job lookup").is_some());
+
+        job.delete().await.expect("deleted");
+        let purged = job.purge().await.expect("purged");
+        assert_eq!((purged.runs, purged.size), (2, 3 + 12));
+        assert!(db::Job::deleted(&db, "test-user", "doomed").await.expect("job lookup").is_none());
+        assert!(!job_dir.exists());
+
+        // The unfinished run's id gets used again. None of its output should come along with it.
+        db::Run::create(&db, "test-user", "Doomed", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let reborn = db::Run::create(&db, "test-user", "Doomed", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        assert_eq!(reborn.run_db_id, unfinished.run_db_id);
+        assert_eq!(reborn.log_len(), 0);
+        reborn.add_output(serve::OutKind::Stdout, b"new\n", None, 0).await.expect("output added");
+        assert_eq!(std::fs::read(reborn.log_path()).expect("log written"), b"new\n");
+        reborn.complete(db::ExitStatus::Exited(0)).await.expect("completed");
+    }
+
+    #[tokio::test]
+    async fn split_secrets_masked() {
+        let (db, _db_path) = test_db().await;
+        let mut settings = db::Settings::load(&db).await.expect("settings");
+        settings.set_redaction(crate::redact::RedactionRules { logs: true, ..Default::default() }).await.expect("redaction set");
+        let env = vec![(MaybeUTF8::new(OsString::from("API_TOKEN")), MaybeUTF8::new(OsString::from("sekrit123")))];
+        let run = db::Run::create(&db, "test-user", "Leaky", None, "true".to_string(), env, None).await.expect("db::Run create worked");
+        // Like a spooled run, chopped up without regard for lines
+        run.add_output(serve::OutKind::Stdout, b"ok\ntoken is sek", None, 0).await.expect("output added");
+        run.add_output(serve::OutKind::Stdout, b"rit123 and", None, 15).await.expect("output added");
+        run.add_output(serve::OutKind::Stdout, b" sekrit123\n", None, 25).await.expect("output added");
+        run.complete(db::ExitStatus::Exited(0)).await.expect("completed");
+        assert_eq!(std::fs::read(run.log_path()).expect("log"), b"ok\ntoken is ********* and *********\n");
+    }
+
+    #[tokio::test]
+    async fn rename_and_merge_jobs() {
+        let (db, db_path) = test_db().await;
+        let run = db::Run::create(&db, "test-user", "Old Name", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        run.add_output(serve::OutKind::Stdout, b"old\n", None, 0).await.expect("output added");
+        run.complete(db::ExitStatus::Exited(0)).await.expect("completed");
+
+        let job = run.job.rename(Some("New Name"), Some("new-id")).await.expect("renamed");
+        assert!(!db_path.path().join(run.job.job_path()).exists());
+        let renamed = job.run(&run.run_id).await.expect("run moved with the job");
+        assert!(renamed.log_path().starts_with(db_path.path().join(job.job_path())));
+        assert_eq!(renamed.size(), 4);
+        // The old id still gets there, for clients that haven't changed SYNCRON_NAME
+        assert_eq!(db::Job::ensure(&db, "test-user", "Old Name", None).await.expect("ensured").job_id, job.job_id);
+        assert_eq!(db::Job::new(&db, "test-user", "old-name").await.expect("job lookup").expect("aliased").id, "new-id");
+
+        // Someone changed SYNCRON_NAME instead, and the history got split. Make one run collide.
+        let other = db::Run::create(&db, "test-user", "Split", None, "true".to_string(), vec![], Some(run.date.timestamp_millis())).await.expect("db::Run create worked");
+        other.add_output(serve::OutKind::Stdout, b"split\n", None, 0).await.expect("output added");
+        other.complete(db::ExitStatus::Exited(0)).await.expect("completed");
+        // And one that's still going
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let going = db::Run::create(&db, "test-user", "Split", None, "true".to_string(), vec![], None).await.expect("db::Run create worked");
+        going.add_output(serve::OutKind::Stdout, b"before\n", None, 0).await.expect("output added");
+        assert_eq!(job.merge(&other.job).await.expect("merged"), 2);
+        let runs = job.runs(None, None, None).await.expect("runs");
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[1].date.timestamp_millis(), run.date.timestamp_millis() + 1);
+        assert_eq!(runs[1].size(), 6);
+
+        // The client doesn't know anything happened
+        let going = db::Run::from_client_id(&db, going.client_id.unwrap()).await.expect("still there");
+        assert_eq!(going.job.job_id, job.job_id);
+        going.add_output(serve::OutKind::Stdout, b"after\n", None, 7).await.expect("output added");
+        going.complete(db::ExitStatus::Exited(0)).await.expect("completed");
+        assert_eq!(std::fs::read(going.log_path()).expect("log"), b"before\nafter\n");
+        assert_eq!(db::Job::ensure(&db, "test-user", "Split", None).await.expect("ensured").job_id, job.job_id);
+        assert_eq!(db::Job::jobs(&db).await.expect("jobs").len(), 1);
+
+        // Going back to an old id is fine. Taking one that's an alias of another job isn't.
+        let job = job.rename(None, Some("old-name")).await.expect("renamed back");
+        assert_eq!(db::Job::new(&db, "test-user", "new-id").await.expect("job lookup").expect("aliased").id, "old-name");
+        let third = db::Job::ensure(&db, "test-user", "Third", None).await.expect("ensured");
+        assert!(third.rename(None, Some("split")).await.is_err());
+        assert!(third.rename(None, Some("old-name")).await.is_err());
+        assert!(job.merge(&job).await.is_err());
+    }
+
+    #[test]
+    fn splay() {
+        let max = std::time::Duration::from_secs(60);
+        let stable = super::splay(max, Some("my-job")).expect("stable splay");
+        assert!(stable <= max);
+        assert_eq!(super::splay(max, Some("my-job")).expect("stable splay again"), stable);
+        for _ in 0..100 {
+            assert!(super::splay(max, None).expect("random splay") <= max);
+        }
+        assert_eq!(super::splay(std::time::Duration::ZERO, None).expect("zero splay"), std::time::Duration::ZERO);
+    }
+
     #[tokio::test]
     async fn integration() {
         let (db, db_path) = test_db().await;
@@ -372,9 +1295,9 @@ mod tests {
         let _serve = tokio::spawn({ let db = db.clone(); async move { serve::serve(32923, &db, true).await.unwrap(); }});
         let _client = tokio::spawn(async move {
             tokio::time::sleep(std::time::Duration::from_millis(100)).await; // HACK
-            let job = crate::client::Job::new("http://127.0.0.1:32923/".parse().unwrap(), "test-user", "My Job", Some("my-id"), None, &cmd).await.unwrap();
+            let job = crate::client::Job::new("http://127.0.0.1:32923/".parse().unwrap(), Job::create_req("test-user", "My Job", Some("my-id"), &cmd), None, std::time::Duration::from_secs(30), Tee::Never, &cmd).await.unwrap().expect("not skipped");
             let log_path = sqlx::query!("SELECT log FROM run WHERE client_id = ?", job.id).fetch_one(db.sql()).await.expect("SELECT log FROM run").log;
-            job.run().await.expect("job ran");
+            assert_eq!(job.run().await.expect("job ran"), db::ExitStatus::Exited(0));
             assert_file_eq!(&db_path.join(&log_path), "a simple test\n");
 
             let jobs: Vec<serve::JobInfo> = serde_json::from_str(&job.api.get("/jobs").await.expect("GET /jobs")).expect("GET /jobs parse");
@@ -387,17 +1310,58 @@ mod tests {
             assert_eq!(runs.len(), 1);
             println!("runs: {:?}", runs);
             assert_eq!(runs[0].status, Some(db::ExitStatus::Exited(0)));
+            assert!(runs[0].usage.is_some(), "run should have resource usage");
 
             let run: serve::RunInfoFull = serde_json::from_str(&job.api.get(&runs[0].url.as_ref().expect("runs[0].url")).await.expect("GET run")).expect("GET run parse");
             assert_eq!(run.cmd, cmd.to_string());
             assert!(run.env.contains(&(MaybeUTF8::new(OsString::from("MY_ENV_VAR")), MaybeUTF8::new(OsString::from("some value")))));
             assert_eq!(run.log.expect("run.log"), "a simple test\n");
 
+            // Frames that got swapped on the way: the server only counts what it has without a gap
+            let other = crate::client::Job::new("http://127.0.0.1:32923/".parse().unwrap(), Job::create_req("test-user", "Swapped", None, &cmd), None, std::time::Duration::from_secs(30), Tee::Never, &cmd).await.unwrap().expect("not skipped");
+            let frame = |seq, offset, chunk: &[u8]| serve::OutputFrame { kind: serve::OutKind::Stdout, seq, offset, chunk: chunk.to_vec() }.to_bytes();
+            let path = format!("/run/{}/output", other.id);
+            let resp: serve::OutputResp = serde_json::from_str(&other.api.post(&path, &[frame(2, 6, b"ghi"), frame(1, 3, b"def")].concat()).await.expect("POST output")).expect("parse");
+            assert_eq!(resp.received, 0);
+            let resp: serve::OutputResp = serde_json::from_str(&other.api.post(&path, &[frame(1, 3, b"def"), frame(0, 0, b"abc")].concat()).await.expect("POST output")).expect("parse");
+            assert_eq!(resp.received, 9);
+
             job.api.post("/shutdown", &[]).await.expect("POST /shutdown");
         }).await.unwrap();
         _serve.await.unwrap();
     }
 
+    #[tokio::test]
+    async fn spooled_retries() {
+        let (db, _db_path) = test_db().await;
+        let cmd = Command::Shell("false".into());
+        let _serve = tokio::spawn({ let db = db.clone(); async move { serve::serve(32925, &db, true).await.unwrap(); }});
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await; // HACK
+            let api = Api::new("http://127.0.0.1:32925/".parse().unwrap()).unwrap();
+            let dir = tempfile::Builder::new().prefix("syncron-spool-test").tempdir().unwrap();
+            let spool = crate::spool::Spool::new(dir.path().to_path_buf());
+            let req = |start| serve::CreateRunReq { start: Some(start), ..Job::create_req("test-user", "Spooled Retries", None, &cmd) };
+            let first = spool.create_run(&req(1_000_000), None).expect("spool first attempt");
+            assert_eq!(fallback_run(None, std::time::Duration::from_secs(30), &cmd, Some(&first)).await.expect("first attempt ran"), db::ExitStatus::Exited(1));
+            let retry = spool.create_run(&req(2_000_000), Some(&first)).expect("spool retry");
+            assert_eq!(fallback_run(None, std::time::Duration::from_secs(30), &cmd, Some(&retry)).await.expect("retry ran"), db::ExitStatus::Exited(1));
+            drop(retry);
+            assert_eq!(spool.flush(&api).await.expect("flush"), 0, "the retry has to wait for the first attempt");
+            drop(first);
+            assert_eq!(spool.flush(&api).await.expect("flush"), 2);
+
+            let runs = sqlx::query!("SELECT run_id, attempt_of, attempt FROM run ORDER BY start").fetch_all(db.sql()).await.expect("SELECT FROM run");
+            assert_eq!(runs.len(), 2);
+            assert_eq!(runs[0].attempt_of, None);
+            assert_eq!(runs[1].attempt_of, Some(runs[0].run_id));
+            assert_eq!(runs[1].attempt, Some(2));
+
+            api.post("/shutdown", &[]).await.expect("POST /shutdown");
+        }).await.unwrap();
+        _serve.await.unwrap();
+    }
+
     #[tokio::test]
     async fn timeout() {
         trace!("Testing");
@@ -407,9 +1371,9 @@ mod tests {
         let _serve = tokio::spawn({let db = db.clone(); async move { serve::serve(32924, &db, true).await.unwrap(); }});
         let _client = tokio::spawn(async move {
             tokio::time::sleep(std::time::Duration::from_millis(100)).await; // HACK
-            let job = crate::client::Job::new("http://127.0.0.1:32924/".parse().unwrap(), "test-user", "My Bad Job", None, Some(std::time::Duration::from_millis(1500)), &cmd).await.unwrap();
+            let job = crate::client::Job::new("http://127.0.0.1:32924/".parse().unwrap(), Job::create_req("test-user", "My Bad Job", None, &cmd), Some(std::time::Duration::from_millis(1500)), std::time::Duration::from_secs(30), Tee::Never, &cmd).await.unwrap().expect("not skipped");
             let log_path = sqlx::query!("SELECT log FROM run WHERE client_id = ?", job.id).fetch_one(db.sql()).await.expect("SELECT log FROM run").log;
-            job.run().await.expect("job ran");
+            assert_eq!(job.run().await.expect("job ran"), db::ExitStatus::ClientTimeout(nix::sys::signal::Signal::SIGTERM as i32));
             assert_eq!(db_path.join(&log_path).exists(), false);
 
             job.api.post("/shutdown", &[]).await.expect("POST /shutdown");
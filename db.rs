@@ -10,6 +10,7 @@ use tokio::io::AsyncWriteExt;
 
 use crate::event::Broker;
 use crate::maybe_utf8::MaybeUTF8;
+use crate::redact::{RedactionRules, Redactor};
 use crate::serve;
 use crate::wrap;
 
@@ -18,6 +19,7 @@ pub struct Db {
     db_path: PathBuf,
     sql: sqlx::SqlitePool,
     broker: Broker,
+    log_writers: LogWriters,
 }
 
 impl Db {
@@ -34,6 +36,7 @@ impl Db {
         let db = Db{ db_path: db_path.into(),
                      sql: pool,
                      broker: Broker::new(),
+                     log_writers: Default::default(),
         };
         db.migrate().await?;
         Ok(db)
@@ -64,6 +67,126 @@ pub struct Job {
 pub struct JobSettings {
     #[serde(default)]
     pub retention: JobRetention,
+    #[serde(default)]
+    pub overlap: Overlap,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    #[serde(default)]
+    pub success: SuccessPolicy,
+    #[serde(default)]
+    pub log_rules: Vec<LogRule>,
+}
+
+// What counts as a successful run. The defaults are what we've always done.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SuccessPolicy {
+    pub exit_codes:    Vec<i32>, // Exit codes that mean success
+    pub warning_codes: Vec<i32>, // Exit codes that mean it finished, but something was off
+    pub empty_output:  bool,     // Any other exit code is fine too, as long as the job didn't print anything
+    pub signals:       bool,     // Getting killed by a signal is fine (crashing with a core dump never is)
+    pub timeout_fails: bool,     // Hitting --timeout is a failure
+}
+
+impl Default for SuccessPolicy {
+    fn default() -> Self {
+        // cron doesn't care about exit status and so a lot of cron jobs return false (especially conditional ones).
+        // If they didn't print anything then nobody would have heard about it, so it's not a failure.
+        SuccessPolicy { exit_codes: vec![0], warning_codes: vec![], empty_output: true, signals: false, timeout_fails: true }
+    }
+}
+
+impl SuccessPolicy {
+    pub fn outcome(&self, status: ExitStatus, log_len: u64) -> Outcome {
+        let ok = |ok| if ok { Outcome::Success } else { Outcome::Failure };
+        match status {
+            ExitStatus::Exited(code) if self.exit_codes.contains(&code)    => Outcome::Success,
+            ExitStatus::Exited(code) if self.warning_codes.contains(&code) => Outcome::Warning,
+            ExitStatus::Exited(_)          => ok(self.empty_output && log_len == 0),
+            ExitStatus::Signal(_)          |
+            ExitStatus::Interrupted(_)     => ok(self.signals),
+            ExitStatus::ClientTimeout(_)   => ok(!self.timeout_fails),
+            ExitStatus::Skipped            => Outcome::Success, // Nothing actually went wrong
+            ExitStatus::CoreDump(_)        |
+            ExitStatus::ServerTimeout      |
+            ExitStatus::Superseded(_)      => Outcome::Failure,
+        }
+    }
+}
+
+// How a run went. Stored as text in the run table's `outcome` column. Worse ones sort later.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Warning, // It finished, but something was off (a warning exit code or a warning log rule matched)
+    Failure,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self { Outcome::Success => "success",
+                     Outcome::Warning => "warning",
+                     Outcome::Failure => "failure" }
+    }
+}
+
+impl std::str::FromStr for Outcome {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s { "success" => Ok(Outcome::Success),
+                  "warning" => Ok(Outcome::Warning),
+                  "failure" => Ok(Outcome::Failure),
+                  _         => Err(format!("Bad outcome: {}", s)) }
+    }
+}
+
+impl From<LogLevel> for Outcome {
+    fn from(level: LogLevel) -> Outcome {
+        match level { LogLevel::Warning => Outcome::Warning,
+                      LogLevel::Failure => Outcome::Failure }
+    }
+}
+
+// Checked against each line of the log when a run completes. Plenty of scripts exit 0 even after they print "ERROR".
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct LogRule {
+    pub pattern: String, // Regex
+    pub level:   LogLevel,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Warning, // Worth pointing out, but the run can still succeed
+    Failure, // The run failed, whatever its exit status says
+}
+
+// A line of the log that a LogRule matched.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct LogMatch {
+    pub line:   u64,    // Starting at 1
+    pub offset: u64,    // Where the line starts in the log
+    pub level:  LogLevel,
+    pub text:   String, // The line itself (lossy, and cut short if it's long)
+}
+
+// Retrying failed runs happens on the client. `syncron exec --retries/--retry-delay` override these.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, Default, PartialEq)]
+pub struct RetrySettings {
+    pub retries: Option<u32>, // How many more times to try after the first attempt fails
+    pub delay:   Option<u64>, // Seconds to wait between attempts
+}
+
+// What to do when a job gets started while a previous run of it is still going.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Overlap {
+    #[default]
+    Allow,        // Let them both run
+    Skip,         // Don't start the new one. It gets recorded with ExitStatus::Skipped.
+    Queue,        // The new one waits for the previous one to finish
+    KillPrevious, // Ask the previous one to stop, then start the new one once it has
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
@@ -99,6 +222,39 @@ pub struct RunInfo {
     pub env:    Vec<(MaybeUTF8,MaybeUTF8)>,
     pub end:    Option<chrono::DateTime<chrono::Local>>,
     pub status: Option<ExitStatus>,
+    pub outcome: Option<Outcome>,
+    pub log_gaps: Vec<LogGap>,
+    pub log_matches: Vec<LogMatch>,
+    pub usage:  Option<Usage>,
+    pub attempt: Option<Attempt>,
+    pub scheduled: Option<chrono::DateTime<chrono::Local>>, // Only if the client held off on starting it (--splay)
+    pub metrics: Metrics,
+}
+
+// Retries of a failed run are runs of their own that point back to the first attempt.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct Attempt {
+    pub number: u32,    // The first attempt would be 1, but it doesn't get an Attempt
+    pub of:     String, // run_id of the first attempt
+}
+
+// A hole in the log: output the client sent that never made it to us.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub struct LogGap {
+    pub at:      u64, // Where in the log the missing output should have been
+    pub missing: u64, // Number of bytes that went missing
+}
+
+// What the job (and everything it waited on) used, according to getrusage(2) on the client.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct Usage {
+    pub user_ms:              u64,
+    pub system_ms:            u64,
+    pub max_rss:              u64, // Bytes. Of the biggest single process, not the total.
+    pub block_in:             u64, // Number of block reads/writes
+    pub block_out:            u64,
+    pub voluntary_switches:   u64,
+    pub involuntary_switches: u64,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
@@ -107,9 +263,53 @@ pub enum ExitStatus {
     Signal(i32),
     CoreDump(i32),
     ServerTimeout, // Server didn't get a heartbeat for some period of time
-    ClientTimeout, // Client hit timeout waiting for child to complete
+    ClientTimeout(i32), // Client hit timeout waiting for child to complete. The signal it finally took to kill it.
+    Interrupted(i32),   // Someone sent the client this signal, which it passed on to the job
+    Superseded(i32),    // A newer run asked this one to stop (Overlap::KillPrevious). The signal it took to stop it.
+    Skipped,            // Never ran because a previous run was still going (Overlap::Skip)
+}
+
+// What the job told us about how far along it is (serve::Control). Replaces our guess when there's anything in it.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct ProgressReport {
+    pub done:  Option<f64>,
+    pub total: Option<f64>,    // Without this, `done` can't be turned into a percentage
+    pub stage: Option<String>, // What it's doing at the moment
 }
 
+// A file the job left in $SYNCRON_ARTIFACTS. They live in the "artifacts" dir next to the run's log.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct Artifact {
+    pub name: String, // Path relative to the artifacts dir ("screenshots/home.png")
+    pub size: u64,
+}
+
+// Everything in `dir` (the client uses this to find what to upload, too).
+pub fn list_artifacts(dir: &Path) -> Result<Vec<Artifact>, Box<dyn Error>> {
+    let mut artifacts = vec![];
+    match find_artifacts(dir, Path::new(""), &mut artifacts) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}, // The job didn't leave any
+        Err(e) => Err(wrap(&e, &format!("read {}", dir.to_string_lossy())))?,
+        Ok(())  => {},
+    }
+    Ok(artifacts)
+}
+
+fn find_artifacts(dir: &Path, prefix: &Path, found: &mut Vec<Artifact>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = prefix.join(entry.file_name());
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() { find_artifacts(&entry.path(), &name, found)? }
+        else { found.push(Artifact { name: name.to_string_lossy().into_owned(), size: metadata.len() }) }
+    }
+    Ok(())
+}
+
+// Numbers the job reported about a run (serve::Control::Metric), by name. The last one reported for a name wins.
+pub type Metrics = std::collections::BTreeMap<String, f64>;
+
 // progress files in the run dir
 #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
 struct ProgressChunk {
@@ -166,11 +366,15 @@ impl Job {
         let id = id.unwrap_or(&slug(name)).to_owned();
         if user.is_empty() || user.contains("/") || user.starts_with(".") { Err(format!("Bad user"))? }
         if id.is_empty()   || id.contains("/")   || id.starts_with(".")   { Err(format!("Bad id"))? }
+        let id = Job::alias(db, user, &id).await?.unwrap_or(id);
         let user_id = user_id(db, user).await?;
         let exists = sqlx::query!("SELECT job_id FROM job WHERE user_id = ? AND id = ?", user_id, id)
             .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "Job ensure existence SELECT"))?.is_some();
         let changed = sqlx::query!(r"INSERT INTO job (user_id, id, name) VALUES (?, ?, ?) ON CONFLICT DO NOTHING RETURNING true", user_id, id, name)
             .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "Job ensure INSERT"))?.is_some();
+        // A deleted job that runs again comes back (runs and all) if it hasn't been purged yet.
+        let revived = sqlx::query!(r"UPDATE job SET deleted = NULL WHERE user_id = ? AND id = ? AND deleted IS NOT NULL RETURNING true", user_id, id)
+            .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "Job ensure revive"))?.is_some();
         let job = sqlx::query!("SELECT job_id, last_progress, settings FROM job WHERE user_id = ? AND id = ?", user_id, id)
             .fetch_one(db.sql()).await.map_err(|e| wrap(&e, "Job ensure SELECT"))?;
 
@@ -182,7 +386,7 @@ impl Job {
                  last_progress_json: job.last_progress,
                  settings: serde_sqlite_jsonb::from_reader(&*job.settings).unwrap_or(JobSettings::default()),
         };
-        if changed && !exists { db.broker.send_job_create(&job).await }
+        if changed && !exists || revived { db.broker.send_job_create(&job).await }
         if changed &&  exists { db.broker.send_job_update(&job).await }
         Ok(job)
     }
@@ -190,10 +394,11 @@ impl Job {
     pub async fn new(db: &Db, user: &str, id: &str) -> Result<Option<Job>, Box<dyn Error>> {
         if user.is_empty() || user.contains("/") || user.starts_with(".") { Err(format!("Bad user"))? }
         if id.is_empty()   || id.contains("/")   || id.starts_with(".")   { Err(format!("Bad id"))? }
+        let id = &Job::alias(db, user, id).await?.unwrap_or(id.to_string());
         Ok(match sqlx::query!(r"SELECT j.job_id, j.name, j.last_progress, j.settings as settings
                                    FROM job j
                                    JOIN user u ON u.user_id = j.user_id
-                                  WHERE u.name = ? AND j.id = ?",
+                                  WHERE u.name = ? AND j.id = ? AND j.deleted IS NULL",
                               user, id)
            .fetch_optional(db.sql()).await? {
                Some(job) => {
@@ -210,6 +415,20 @@ impl Job {
            })
     }
 
+    // Renamed jobs and jobs that were merged into other jobs leave their old ids behind. This is the current id of
+    // the job that `id` used to be, if it was one of those.
+    pub async fn alias(db: &Db, user: &str, id: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(sqlx::query!(r"SELECT j.id FROM job_alias a JOIN job j ON j.job_id = a.job_id JOIN user u ON u.user_id = a.user_id WHERE u.name = ? AND a.id = ?", user, id)
+           .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "job alias"))?.map(|a| a.id))
+    }
+
+    // Like new(), but for a job that's been deleted and hasn't been purged yet.
+    pub async fn deleted(db: &Db, user: &str, id: &str) -> Result<Option<Job>, Box<dyn Error>> {
+        let Some(job) = sqlx::query!(r"SELECT j.job_id FROM job j JOIN user u ON u.user_id = j.user_id WHERE u.name = ? AND j.id = ? AND j.deleted IS NOT NULL", user, id)
+            .fetch_optional(db.sql()).await.map_err(|e| wrap(&e, "deleted job"))? else { return Ok(None) };
+        Ok(Some(Job::from_id(db, job.job_id).await?))
+    }
+
     pub async fn from_id(db: &Db, job_id: i64) -> Result<Job, Box<dyn Error>> {
         let job = sqlx::query!(r"SELECT j.job_id, j.name, u.name as user, j.id, j.last_progress, j.settings
                                    FROM job j
@@ -227,7 +446,7 @@ impl Job {
     }
 
     pub async fn jobs(db: &Db) -> Result<Vec<Job>, Box<dyn Error>> {
-        Ok(sqlx::query!("SELECT j.job_id, j.id as id, j.name as name, u.name as user, j.last_progress, j.settings FROM job j JOIN user u ON u.user_id = j.user_id")
+        Ok(sqlx::query!("SELECT j.job_id, j.id as id, j.name as name, u.name as user, j.last_progress, j.settings FROM job j JOIN user u ON u.user_id = j.user_id WHERE j.deleted IS NULL")
            .fetch_all(db.sql()).await.map_err(|e| wrap(&e, "get jobs"))?.iter()
            .map(|job|  Job { db: db.clone(),
                              user: job.user.clone(),
@@ -298,13 +517,93 @@ impl Job {
         Run::from_run_id(self, run_id).await
     }
 
-    pub async fn successes(&self, before: Option<u64>, after:Option<u64>) -> Result<Vec<(i64, Option<bool>)>, Box<dyn Error>> {
+    // Runs that haven't completed. Ones whose clients have gone quiet get timed out instead of counting.
+    pub async fn running(&self) -> Result<Vec<Run>, Box<dyn Error>> {
+        let runs = sqlx::query!("SELECT r.run_id, r.start, r.end, r.client_id, r.log FROM run r WHERE r.job_id = ? AND r.status IS NULL", self.job_id)
+           .fetch_all(self.db.sql()).await.map_err(|e| wrap(&e, "get running"))?.iter()
+           .map(|run|  Run { job: self.clone(),
+                             date: time_from_timestamp_ms(run.start),
+                             duration_ms: run.end.and_then(|e| (e as u64).checked_sub(run.start as u64)),
+                             run_id: time_string_from_timestamp_ms(run.start),
+                             run_db_id: run.run_id,
+                             client_id: run.client_id.as_ref().and_then(|id| id.parse::<u128>().ok()),
+                             log_path: run.log.clone().into(), })
+           .collect::<Vec<_>>();
+        let mut running = vec![];
+        for run in runs {
+            if run.info().await?.status.is_none() {
+                running.push(run);
+            }
+        }
+        Ok(running)
+    }
+
+    // One entry per invocation (retries get folded into their first attempt). It only failed if every attempt
+    // failed, and it's still going if any attempt is. None means it's still going. Pruning can take the first attempt
+    // away (attempt_of is just a number, not a foreign key), so each entry goes by the oldest attempt that's left.
+    pub async fn successes(&self, before: Option<u64>, after:Option<u64>) -> Result<Vec<(i64, Option<Outcome>)>, Box<dyn Error>> {
         let (before, after) = (before.map(|n| n as i64).unwrap_or(i64::MAX), after.map(|n| n as i64).unwrap_or(0i64));
-        Ok(sqlx::query!("SELECT start, success FROM run WHERE job_id = ? AND start > ? AND start < ? ORDER BY start",
-                        self.job_id, after, before)
+        sqlx::query!(r#"SELECT MIN(r.start) AS "start!: i64",
+                               CASE WHEN SUM(r.outcome = 'success') > 0 THEN 'success'
+                                    WHEN SUM(r.outcome = 'warning') > 0 THEN 'warning'
+                                    WHEN SUM(r.outcome IS NULL)     > 0 THEN NULL
+                                    ELSE 'failure' END AS "outcome?: String"
+                          FROM run r WHERE r.job_id = ?
+                          GROUP BY COALESCE(r.attempt_of, r.run_id)
+                          HAVING MIN(r.start) > ? AND MIN(r.start) < ? ORDER BY MIN(r.start)"#,
+                     self.job_id, after, before)
            .fetch_all(self.db.sql()).await.map_err(|e| wrap(&e, "get hist"))?.iter()
-           .map(|run| (run.start, run.success.map(|s| s != 0)))
-           .collect())
+           .map(|run| Ok((run.start, run.outcome.as_deref().map(str::parse).transpose()?)))
+           .collect()
+    }
+
+    // Every run that reported metrics, oldest first: (start, metrics). `name` limits it to runs that reported that one
+    // metric, and to just that metric.
+    pub async fn metrics(&self, name: Option<&str>, before: Option<u64>, after:Option<u64>) -> Result<Vec<(i64, Metrics)>, Box<dyn Error>> {
+        let (before, after) = (before.map(|n| n as i64).unwrap_or(i64::MAX), after.map(|n| n as i64).unwrap_or(0i64));
+        let mut series = sqlx::query!(r#"SELECT start, metrics AS "metrics!" FROM run WHERE job_id = ? AND metrics IS NOT NULL AND start > ? AND start < ? ORDER BY start"#,
+                                      self.job_id, after, before)
+           .fetch_all(self.db.sql()).await.map_err(|e| wrap(&e, "get metrics"))?.iter()
+           .map(|run| Ok((run.start, serde_json::from_str::<Metrics>(&run.metrics)?)))
+           .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        if let Some(name) = name {
+            series.retain_mut(|(_, metrics)| { metrics.retain(|n, _| n == name); !metrics.is_empty() });
+        }
+        Ok(series)
+    }
+
+    // How a run that ended with `status` and left the log at `log_path` went, and the lines of the log that the log
+    // rules matched. The worst of the success policy and the log rules wins.
+    async fn judge(&self, status: ExitStatus, log_path: &Path) -> (Outcome, Vec<LogMatch>) {
+        let log_len = log_path.metadata().map(|m| m.len()).unwrap_or(0);
+        let log_matches = scan_log(log_path, &self.settings.log_rules).await.unwrap_or_else(|e| {
+            warn!("{}/{}: couldn't check {} against the log rules: {}", self.user, self.id, log_path.to_string_lossy(), e);
+            vec![]
+        });
+        let outcome = log_matches.iter().map(|m| Outcome::from(m.level)).fold(self.settings.success.outcome(status, log_len), Outcome::max);
+        (outcome, log_matches)
+    }
+
+    // Runs are judged when they complete, so changing the job's success policy only affects new runs. This judges the
+    // old ones again. Returns how many of them changed.
+    pub async fn reevaluate_successes(&self) -> Result<usize, Box<dyn Error>> {
+        let runs = sqlx::query!(r#"SELECT run_id, status AS "status!", outcome, log, log_matches FROM run WHERE job_id = ? AND status IS NOT NULL"#, self.job_id)
+           .fetch_all(self.db.sql()).await.map_err(|e| wrap(&e, "get statuses"))?;
+        let mut changed = 0;
+        for run in runs {
+            let status: ExitStatus = match serde_json::from_str(&run.status) {
+                Ok(status) => status,
+                Err(e)     => { warn!("{}/{}: run {} has a bad status {:?}: {}", self.user, self.id, run.run_id, run.status, e); continue },
+            };
+            let (outcome, log_matches) = self.judge(status, &self.db.db_path.join(&run.log)).await;
+            let log_matches_json = match log_matches.len() { 0 => None, _ => Some(serde_json::to_string(&log_matches)?) };
+            if run.outcome.as_deref() == Some(outcome.as_str()) && run.log_matches == log_matches_json { continue }
+            let (success, outcome) = (outcome != Outcome::Failure, outcome.as_str());
+            sqlx::query!("UPDATE run SET success = ?, outcome = ?, log_matches = ? WHERE run_id = ?", success, outcome, log_matches_json, run.run_id).execute(self.db.sql()).await?;
+            changed += 1;
+        }
+        debug!("{}/{}: re-evaluated success, {} runs changed", self.user, self.id, changed);
+        Ok(changed)
     }
 
     pub fn last_progress(&self) -> Result<Option<Vec<ProgressStat>>, Box<dyn Error>> { // deserialize this lazily. We only need it sometimes.
@@ -315,6 +614,7 @@ impl Job {
     }
 
     pub async fn update_settings(&self, new_settings: &JobSettings) -> Result<(), Box<dyn Error>> {
+        compile_log_rules(&new_settings.log_rules)?; // Don't save rules that won't compile
         let json = serde_json::to_string(&new_settings)?;
         sqlx::query!("UPDATE job SET settings = jsonb(?) WHERE job_id = ?", json, self.job_id).execute(self.db.sql()).await?;
         Ok(())
@@ -330,7 +630,7 @@ impl Job {
         let runs = self.runs(None, None, None).await?;
         debug!("Considering {} [{} runs]", self.name, runs.len());
         let mut total = 0;
-        let sizes: Vec<(usize,usize)> = runs.iter().map(|r| { let size = r.log_len() as usize; total += size; (size, total) }).collect();
+        let sizes: Vec<(usize,usize)> = runs.iter().map(|r| { let size = r.size() as usize; total += size; (size, total) }).collect();
         let now = chrono::Local::now();
         let mut pruned = vec![];
         let mut limiter = NotSoFast::new(std::time::Duration::from_millis(1000/10));
@@ -394,8 +694,154 @@ impl Job {
         };
         self.prune(force_stats).await
     }
+
+    // Hides the job right away but leaves everything where it is for DELETE_UNDO_MS, in case it was a mistake.
+    // purge_deleted() gets rid of it for real after that. Returns when that will be.
+    pub async fn delete(&self) -> Result<i64, Box<dyn Error>> {
+        let now = chrono::Local::now().timestamp_millis();
+        sqlx::query!("UPDATE job SET deleted = ? WHERE job_id = ?", now, self.job_id).execute(self.db.sql()).await.map_err(|e| wrap(&e, "delete job"))?;
+        self.db.broker.send_job_delete(self).await;
+        Ok(now + DELETE_UNDO_MS)
+    }
+
+    pub async fn undelete(&self) -> Result<(), Box<dyn Error>> {
+        sqlx::query!("UPDATE job SET deleted = NULL WHERE job_id = ?", self.job_id).execute(self.db.sql()).await.map_err(|e| wrap(&e, "undelete job"))?;
+        self.db.broker.send_job_create(self).await;
+        Ok(())
+    }
+
+    async fn is_deleted(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(sqlx::query!("SELECT deleted FROM job WHERE job_id = ?", self.job_id).fetch_optional(self.db.sql()).await?.is_some_and(|j| j.deleted.is_some()))
+    }
+
+    // Gets rid of a deleted job for good: every run (files and all), then the job itself. Jobs can have millions of
+    // runs so this sends progress events as it goes, like pruning does. It stops if the job gets undeleted.
+    pub async fn purge(&self) -> Result<RunCount, Box<dyn Error>> {
+        let runs = self.runs(None, None, None).await?;
+        info!("Purging {}/{} [{} runs]", self.user, self.id, runs.len());
+        let mut limiter = NotSoFast::new(std::time::Duration::from_millis(1000/10));
+        let mut purged = RunCount::default();
+        for run in runs.iter() {
+            if limiter.op(async || self.db.broker.send_delete_progress(self, &purged, runs.len()).await).await.is_some() && !self.is_deleted().await? {
+                warn!("{}/{} was undeleted while it was being purged. Stopping with {} runs left.", self.user, self.id, runs.len() - purged.runs);
+                return Ok(purged);
+            }
+            let size = run.size() as usize;
+            run.delete("job deleted").await.map_err(|e| wrap(&*e, &format!("delete {}/{}/{}", self.user, self.id, run.run_id)))?;
+            purged.runs += 1;
+            purged.size += size;
+        }
+        self.db.broker.send_delete_progress(self, &purged, runs.len()).await;
+        if sqlx::query!("DELETE FROM job WHERE job_id = ? AND deleted IS NOT NULL", self.job_id).execute(self.db.sql()).await
+                .map_err(|e| wrap(&e, "purge job"))?.rows_affected() == 0 {
+            return Ok(purged); // Undeleted (or it ran again) right at the end. It can stay.
+        }
+        // Whatever the runs left behind (directories from runs that never finished, say)
+        let job_path = self.db.db_path.join(self.job_path());
+        if let Err(e) = tokio::fs::remove_dir_all(&job_path).await {
+            if e.kind() != std::io::ErrorKind::NotFound { Err(wrap(&e, &format!("rm -rf {}", job_path.to_string_lossy())))? }
+        }
+        Ok(purged)
+    }
+
+    // Purges the deleted jobs whose undo time is up.
+    pub async fn purge_deleted(db: &Db) -> Result<(), Box<dyn Error>> {
+        let before = chrono::Local::now().timestamp_millis() - DELETE_UNDO_MS;
+        let jobs = sqlx::query!("SELECT job_id FROM job WHERE deleted <= ?", before).fetch_all(db.sql()).await.map_err(|e| wrap(&e, "deleted jobs"))?;
+        for job in jobs {
+            let job = Job::from_id(db, job.job_id).await?;
+            match job.purge().await {
+                Ok(purged) => info!("{}/{}: Purged {} runs ({})", job.user, job.id, purged.runs, crate::human_bytes(purged.size)),
+                Err(e)     => warn!("{}/{}: error purging: {}", job.user, job.id, e),
+            }
+        }
+        Ok(())
+    }
+
+    // Changes the job's name and/or id. A new id moves the job's runs from jobs/<user>/<old id> to jobs/<user>/<new id>
+    // and leaves the old id behind as an alias, so clients that still use it keep ending up here.
+    pub async fn rename(&self, name: Option<&str>, id: Option<&str>) -> Result<Job, Box<dyn Error>> {
+        let job = Job { name: name.unwrap_or(&self.name).to_string(), id: id.unwrap_or(&self.id).to_string(), ..self.clone() };
+        if job.name.is_empty() { Err("Bad name")? }
+        if job.id.is_empty() || job.id.contains("/") || job.id.starts_with(".") { Err("Bad id")? }
+        if job.id == self.id {
+            sqlx::query!("UPDATE job SET name = ? WHERE job_id = ?", job.name, self.job_id).execute(self.db.sql()).await.map_err(|e| wrap(&e, "rename job"))?;
+            self.db.broker.send_job_update(&job).await;
+            return Ok(job);
+        }
+
+        let _lock = START_LOCK.lock().await; // No new runs while things are moving
+        if !self.running().await?.is_empty() { Err(format!("{}/{} has runs that haven't finished. Try again once they have.", self.user, self.id))? }
+        let user_id = user_id(&self.db, &self.user).await?;
+        let (old_path, new_path) = (self.job_path(), job.job_path());
+        let old_prefix = format!("{}/", old_path.to_str().ok_or(format!("bad unicode in {:?}", old_path))?);
+        let new_prefix = format!("{}/", new_path.to_str().ok_or(format!("bad unicode in {:?}", new_path))?);
+        let old_prefix_len = old_prefix.len() as i64;
+
+        let mut transaction = self.db.sql().begin().await.map_err(|e| wrap(&e, "rename transaction"))?;
+        sqlx::query!("UPDATE job SET name = ?, id = ? WHERE job_id = ?", job.name, job.id, self.job_id).execute(&mut *transaction).await
+            .map_err(|e| wrap(&e, &format!("Couldn't rename {}/{} to {} (is it already taken?)", self.user, self.id, job.id)))?;
+        let taken = sqlx::query!("SELECT job_id FROM job_alias WHERE user_id = ? AND id = ? AND job_id != ?", user_id, job.id, self.job_id)
+            .fetch_optional(&mut *transaction).await.map_err(|e| wrap(&e, "rename alias check"))?;
+        if taken.is_some() { Err(format!("Couldn't rename {}/{} to {}: another job used to be called that", self.user, self.id, job.id))? }
+        sqlx::query!("DELETE FROM job_alias WHERE user_id = ? AND id = ?", user_id, job.id).execute(&mut *transaction).await.map_err(|e| wrap(&e, "rename alias"))?; // Going back to an old id
+        sqlx::query!("INSERT INTO job_alias (user_id, id, job_id) VALUES (?, ?, ?)", user_id, self.id, self.job_id).execute(&mut *transaction).await.map_err(|e| wrap(&e, "rename alias"))?;
+        sqlx::query!("UPDATE run SET log = ? || substr(log, ? + 1) WHERE job_id = ? AND substr(log, 1, ?) = ?", new_prefix, old_prefix_len, self.job_id, old_prefix_len, old_prefix)
+            .execute(&mut *transaction).await.map_err(|e| wrap(&e, "rename runs"))?;
+
+        let (from, to) = (self.db.db_path.join(&old_path), self.db.db_path.join(&new_path));
+        if let Err(e) = tokio::fs::rename(&from, &to).await {
+            // No directory just means no runs
+            if e.kind() != std::io::ErrorKind::NotFound { Err(wrap(&e, &format!("mv {} {}", from.to_string_lossy(), to.to_string_lossy())))? }
+        }
+        if let Err(e) = transaction.commit().await {
+            if let Err(e) = tokio::fs::rename(&to, &from).await { warn!("Couldn't put {} back: {}", from.to_string_lossy(), e) }
+            Err(wrap(&e, "rename commit"))?
+        }
+        info!("Renamed {}/{} to {}/{} ({})", self.user, self.id, job.user, job.id, job.name);
+        self.db.broker.send_job_delete(self).await;
+        self.db.broker.send_job_create(&job).await;
+        Ok(job)
+    }
+
+    // Moves all of `from`'s runs into this job and then gets rid of `from`, leaving its id behind as an alias for this
+    // job. The runs' files stay where they are (each run knows where its log is), so even runs that are still going
+    // can be moved. Runs are unique by job and start time, so a run that started in the same millisecond as one of
+    // ours gets nudged later a millisecond at a time until it fits. Returns how many runs were moved.
+    pub async fn merge(&self, from: &Job) -> Result<u64, Box<dyn Error>> {
+        if from.job_id == self.job_id { Err(format!("Can't merge {}/{} into itself", self.user, self.id))? }
+        if from.user != self.user { Err(format!("Can't merge jobs that belong to different users ({} and {})", from.user, self.user))? }
+
+        let _lock = START_LOCK.lock().await; // Nothing new starts in `from` while it's going away
+        let mut transaction = self.db.sql().begin().await.map_err(|e| wrap(&e, "merge transaction"))?;
+        let collisions = sqlx::query!("SELECT run_id, start FROM run WHERE job_id = ? AND start IN (SELECT start FROM run WHERE job_id = ?)", from.job_id, self.job_id)
+            .fetch_all(&mut *transaction).await.map_err(|e| wrap(&e, "merge collisions"))?;
+        for run in collisions {
+            let mut start = run.start + 1;
+            while sqlx::query!("SELECT run_id FROM run WHERE job_id IN (?, ?) AND start = ?", self.job_id, from.job_id, start)
+                    .fetch_optional(&mut *transaction).await.map_err(|e| wrap(&e, "merge collision"))?.is_some() {
+                start += 1;
+            }
+            let shift = start - run.start;
+            sqlx::query!("UPDATE run SET start = ?, end = end + ?, heartbeat = heartbeat + ? WHERE run_id = ?", start, shift, shift, run.run_id)
+                .execute(&mut *transaction).await.map_err(|e| wrap(&e, "merge collision"))?;
+        }
+        let moved = sqlx::query!("UPDATE run SET job_id = ? WHERE job_id = ?", self.job_id, from.job_id)
+            .execute(&mut *transaction).await.map_err(|e| wrap(&e, "merge runs"))?.rows_affected();
+        sqlx::query!("UPDATE job_alias SET job_id = ? WHERE job_id = ?", self.job_id, from.job_id).execute(&mut *transaction).await.map_err(|e| wrap(&e, "merge aliases"))?;
+        sqlx::query!("INSERT INTO job_alias (user_id, id, job_id) SELECT user_id, id, ? FROM job WHERE job_id = ?", self.job_id, from.job_id)
+            .execute(&mut *transaction).await.map_err(|e| wrap(&e, "merge alias"))?;
+        sqlx::query!("DELETE FROM job WHERE job_id = ?", from.job_id).execute(&mut *transaction).await.map_err(|e| wrap(&e, "merge job"))?;
+        transaction.commit().await.map_err(|e| wrap(&e, "merge commit"))?;
+        info!("Merged {}/{} into {}/{} [{} runs]", from.user, from.id, self.user, self.id, moved);
+        self.db.broker.send_job_delete(from).await;
+        self.db.broker.send_job_update(self).await;
+        Ok(moved)
+    }
 }
 
+pub const DELETE_UNDO_MS: i64 = 24 * 60 * 60 * 1000;
+
 struct NotSoFast {
     last: Option<std::time::Instant>,
     min_gap: std::time::Duration,
@@ -416,10 +862,22 @@ impl NotSoFast {
 }
 
 impl Run {
-    pub async fn create(db: &Db, user: &str, name:&str, id:Option<&str>, cmd: String, env: Vec<(MaybeUTF8,MaybeUTF8)>) -> Result<Run, Box<dyn Error>> {
+    // `start` is only passed when the run happened in the past (spooled runs from the client).
+    pub async fn create(db: &Db, user: &str, name:&str, id:Option<&str>, cmd: String, env: Vec<(MaybeUTF8,MaybeUTF8)>, start: Option<i64>) -> Result<Run, Box<dyn Error>> {
         let job = Job::ensure(db, user, name, id).await?;
-        let env_str = serde_json::to_string(&env)?;
-        let date = chrono::Local::now();
+        Run::insert(job, cmd, env, start, None).await
+    }
+
+    // Another attempt at a run that failed. `self` is the first attempt. `start` is only passed for spooled retries.
+    pub async fn retry(&self, cmd: String, env: Vec<(MaybeUTF8,MaybeUTF8)>, start: Option<i64>) -> Result<Run, Box<dyn Error>> {
+        Run::insert(self.job.clone(), cmd, env, start, Some(self.run_db_id)).await
+    }
+
+    async fn insert(job: Job, cmd: String, env: Vec<(MaybeUTF8,MaybeUTF8)>, start: Option<i64>, attempt_of: Option<i64>) -> Result<Run, Box<dyn Error>> {
+        let db = job.db.clone();
+        let redactor = Redactor::new(&Settings::load(&db).await?.redaction, &env)?;
+        let env_str = serde_json::to_string(&redactor.env(&env))?;
+        let date = start.map(time_from_timestamp_ms).unwrap_or_else(chrono::Local::now);
         let start = date.timestamp_millis();
         let run_id = date.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
         let log_path = job.run_path(date).join("log");
@@ -429,16 +887,45 @@ impl Run {
         let client_id: u128 = u128::from_ne_bytes(client_id_bytes);
         let client_id_str = format!("{}", client_id);
         let mut transaction = db.sql().begin().await?;
-        let run_db_id = sqlx::query!("INSERT INTO run (job_id, client_id, cmd, env, log, start) VALUES (?, ?, ?, ?, ?, ?) RETURNING run_id",
-                                     job.job_id, client_id_str, cmd, env_str, log_str, start)
+        let run_db_id = sqlx::query!("INSERT INTO run (job_id, client_id, cmd, env, log, start, attempt_of, attempt)
+                                      VALUES (?, ?, ?, ?, ?, ?, ?, CASE WHEN ?7 IS NULL THEN NULL ELSE COALESCE((SELECT MAX(attempt) FROM run WHERE attempt_of = ?7), 1) + 1 END)
+                                      RETURNING run_id",
+                                     job.job_id, client_id_str, cmd, env_str, log_str, start, attempt_of)
             .fetch_one(&mut *transaction).await?.run_id;
         transaction.commit().await?;
         let run = Run { run_db_id: run_db_id, job: job, date: date.into(), duration_ms: None, run_id: run_id, client_id: Some(client_id), log_path: log_path };
         trace!("created {:?}", run.client_id);
+        run.log_writer().await.lock().await.redactor = Some(redactor); // It knows the secrets from the unredacted env
         db.broker.send_run_create(&run).await;
         Ok(run)
     }
 
+    // Like create(), but first checks the job's Overlap setting against any runs that are still going.
+    pub async fn start(db: &Db, user: &str, name:&str, id:Option<&str>, cmd: String, env: Vec<(MaybeUTF8,MaybeUTF8)>) -> Result<Start, Box<dyn Error>> {
+        let _lock = START_LOCK.lock().await; // So two runs starting at the same time can't both think they're the only one
+        let job = Job::ensure(db, user, name, id).await?;
+        let running = job.running().await?;
+        if running.is_empty() || job.settings.overlap == Overlap::Allow {
+            return Ok(Start::Run(Run::create(db, user, name, id, cmd, env, None).await?));
+        }
+        debug!("{}/{}: {} runs still going. Overlap is {:?}", job.user, job.id, running.len(), job.settings.overlap);
+        Ok(match job.settings.overlap {
+            Overlap::Allow        => unreachable!(),
+            Overlap::Skip         => {
+                let run = Run::create(db, user, name, id, cmd, env, None).await?;
+                run.complete(ExitStatus::Skipped).await?;
+                Start::Skip(run)
+            },
+            Overlap::Queue        => Start::Wait(job),
+            Overlap::KillPrevious => {
+                for run in running.iter() {
+                    run.supersede().await?;
+                }
+                Start::Wait(job)
+            },
+        })
+    }
+
     #[tracing::instrument(skip(db),ret)]
     pub async fn from_client_id(db: &Db, id: u128) -> Result<Run, Box<dyn Error>> {
         let client_id_str = format!("{}",id);
@@ -476,7 +963,7 @@ impl Run {
                                     FROM run r
                                     JOIN job j  ON r.job_id = j.job_id
                                     JOIN user u ON j.user_id = u.user_id
-                                   WHERE start > ?"#, after)
+                                   WHERE start > ? AND j.deleted IS NULL"#, after)
             .fetch_all(db.sql()).await?;
         Ok(run.iter().map(|row| Run { job: Job { user: row.user.clone(),
                                           id: row.id.clone(),
@@ -503,7 +990,7 @@ impl Run {
                                                   FROM run r
                                                   JOIN job j ON r.job_id = j.job_id
                                                   JOIN user u ON j.user_id = u.user_id
-                                                 WHERE r.run_id IN ({})"#, id_list))
+                                                 WHERE r.run_id IN ({}) AND j.deleted IS NULL"#, id_list))
            .fetch_all(db.sql()).await.map_err(|e| wrap(&e, "get runs"))?.iter()
            .map(|row|   Run { job: Job { user: row.user.clone(),
                                          id: row.id.clone(),
@@ -523,7 +1010,7 @@ impl Run {
     }
 
     pub fn log_path(&self)             -> PathBuf {self.job.db.db_path.join(&self.log_path)} // Full path from cwd to log
-    pub fn run_path(&self)             -> PathBuf {self.job.run_path(self.date)}          // Relative path from db to run dir
+    pub fn run_path(&self)             -> PathBuf {self.log_path.parent().map(Path::to_path_buf).unwrap_or_default()} // Relative path from db to run dir (not always under the job's dir--see Job::merge())
 
     async fn mkdir_p(&self) -> Result<(), Box<dyn Error>> {
         DirBuilder::new().recursive(true).create(self.job.db.db_path.join(self.run_path())).await
@@ -531,13 +1018,25 @@ impl Run {
     }
 
     pub async fn get_info(&self) -> Result<RunInfo, Box<dyn Error>> {
-        let run = sqlx::query!(r"SELECT cmd, env, end, status FROM run WHERE run_id = ?", self.run_db_id)
+        let run = sqlx::query!(r#"SELECT cmd, env, end, status, outcome, log_gaps, log_matches, usage, attempt, scheduled, metrics,
+                                         (SELECT f.start FROM run f WHERE f.run_id = run.attempt_of) AS "attempt_of_start?: i64"
+                                    FROM run WHERE run_id = ?"#, self.run_db_id)
             .fetch_one(self.job.db.sql()).await?;
         Ok(RunInfo {
             cmd:    run.cmd,
             env:    serde_json::from_str(&run.env)?,
             end:    run.end.map(|ms| time_from_timestamp_ms(ms).into()),
             status: match run.status { Some(s) => serde_json::from_str(&s)?, _ => None },
+            outcome: run.outcome.as_deref().map(str::parse).transpose()?,
+            log_gaps: match run.log_gaps { Some(g) => serde_json::from_str(&g)?, _ => vec![] },
+            log_matches: match run.log_matches { Some(m) => serde_json::from_str(&m)?, _ => vec![] },
+            usage:  match run.usage { Some(u) => serde_json::from_str(&u)?, _ => None },
+            attempt: match (run.attempt, run.attempt_of_start) {
+                (Some(number), Some(start)) => Some(Attempt { number: number as u32, of: time_string_from_timestamp_ms(start) }),
+                _                           => None,
+            },
+            scheduled: run.scheduled.map(time_from_timestamp_ms),
+            metrics: match run.metrics { Some(m) => serde_json::from_str(&m)?, _ => Metrics::new() },
         })
     }
     pub async fn info(&self) -> Result<RunInfo, Box<dyn Error>> {
@@ -571,20 +1070,310 @@ impl Run {
            .run_id == self.run_db_id)
     }
 
-    pub async fn add_stdout(&self, chunk: &str) -> Result<(), Box<dyn Error>> {
-        self.mkdir_p().await.map_err(|e| wrap(&*e, "add_stdout"))?;
+    pub async fn add_stdout(&self, stream: serve::OutKind, chunk: &[u8]) -> Result<(), Box<dyn Error>> {
+        let writer = self.log_writer().await;
+        let mut writer = writer.lock().await;
+        self.append_log(&mut writer, stream, chunk).await
+    }
+
+    // Clients tag each chunk of output with its byte offset into the log. That lets us throw away chunks we already
+    // have (the client retried a request that actually made it) and put chunks that raced each other back in order.
+    // Chunks that show up before the output in front of them get parked in a "pending" dir next to the log until
+    // the gap fills in.
+    pub async fn add_output(&self, stream: serve::OutKind, chunk: &[u8], seq: Option<u64>, offset: u64) -> Result<(), Box<dyn Error>> {
+        let writer = self.log_writer().await;
+        let mut writer = writer.lock().await;
+        let len = self.log_len();
+        if offset > len {
+            debug!("Run [{}] chunk {:?} at {} is ahead of the log ({}). Parking it.", self.run_db_id, seq, offset, len);
+            let pending_path = self.pending_path();
+            DirBuilder::new().recursive(true).create(&pending_path).await.map_err(|e| wrap(&e, &format!("mkdir -p {}", pending_path.to_string_lossy())))?;
+            let chunk_path = pending_path.join(format!("{}.{}", offset, stream));
+            tokio::fs::write(&chunk_path, chunk).await.map_err(|e| wrap(&e, &format!("write {}", chunk_path.to_string_lossy())))?;
+            return Ok(());
+        }
+        self.append_log_at(&mut writer, stream, chunk, offset).await?;
+
+        // The log just got longer, so maybe some parked chunks fit now.
+        let pending = self.pending().await?;
+        for (offset, stream, chunk_path) in pending {
+            if offset > self.log_len() { break }
+            let chunk = read(&chunk_path).await.map_err(|e| wrap(&e, &format!("read {}", chunk_path.to_string_lossy())))?;
+            self.append_log_at(&mut writer, stream, &chunk, offset).await?;
+            remove_file(&chunk_path).await.map_err(|e| wrap(&e, &format!("rm {}", chunk_path.to_string_lossy())))?;
+        }
+        Ok(())
+    }
+
+    // Only the part of `chunk` past the end of the log gets written, the rest we already have.
+    async fn append_log_at(&self, writer: &mut LogWriter, stream: serve::OutKind, chunk: &[u8], offset: u64) -> Result<(), Box<dyn Error>> {
+        let have = (self.log_len() - offset) as usize;
+        if have >= chunk.len() {
+            debug!("Run [{}] dropping duplicate chunk at {}", self.run_db_id, offset);
+            return Ok(());
+        }
+        self.append_log(writer, stream, &chunk[have..]).await
+    }
+
+    fn pending_path(&self) -> PathBuf { self.log_path().with_file_name("pending") }
+
+    // Parked chunks, sorted by offset. They're named "<offset>.<stream>".
+    async fn pending(&self) -> Result<Vec<(u64, serve::OutKind, PathBuf)>, Box<dyn Error>> {
+        let pending_path = self.pending_path();
+        let mut dir = match tokio::fs::read_dir(&pending_path).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => Err(wrap(&e, &format!("read_dir {}", pending_path.to_string_lossy())))?,
+            Ok(dir) => dir,
+        };
+        let mut pending = vec![];
+        while let Some(entry) = dir.next_entry().await? {
+            match entry.file_name().to_str().and_then(|name| name.split_once('.'))
+                                                   .and_then(|(offset, stream)| Some((offset.parse::<u64>().ok()?, stream.parse::<serve::OutKind>().ok()?))) {
+                Some((offset, stream)) => pending.push((offset, stream, entry.path())),
+                None => warn!("Ignoring junk in {}: {:?}", pending_path.to_string_lossy(), entry.file_name()),
+            }
+        }
+        pending.sort_by_key(|(offset, _, _)| *offset);
+        Ok(pending)
+    }
+
+    // Once the run is over, whatever is still parked is never going to see the output in front of it. Write it out
+    // anyway and keep track of where the holes are. `sent` is how much output the client says it sent in total,
+    // which catches output lost off the end of the log.
+    async fn fill_log_gaps(&self, sent: Option<u64>) -> Result<Vec<LogGap>, Box<dyn Error>> {
+        let writer = self.log_writer().await;
+        let mut writer = writer.lock().await;
+        let mut gaps = vec![];
+        let mut offset = self.log_len(); // Where the client thinks the end of the log is. Differs from log_len() after a gap.
+        let pending = self.pending().await?;
+        for (chunk_offset, stream, chunk_path) in pending {
+            let chunk = read(&chunk_path).await.map_err(|e| wrap(&e, &format!("read {}", chunk_path.to_string_lossy())))?;
+            if chunk_offset > offset {
+                gaps.push(LogGap { at: self.log_len(), missing: chunk_offset - offset });
+                offset = chunk_offset;
+            }
+            let have = ((offset - chunk_offset) as usize).min(chunk.len());
+            self.append_log(&mut writer, stream, &chunk[have..]).await?;
+            offset = offset.max(chunk_offset + chunk.len() as u64);
+            remove_file(&chunk_path).await.map_err(|e| wrap(&e, &format!("rm {}", chunk_path.to_string_lossy())))?;
+        }
+        _ = remove_dir(self.pending_path()).await;
+        if let Some(sent) = sent {
+            if sent > offset { gaps.push(LogGap { at: self.log_len(), missing: sent - offset }) }
+        }
+        if !gaps.is_empty() {
+            warn!("Run [{}] {}/{}/{} lost output: {:?}", self.run_db_id, self.job.user, self.job.name, self.run_id, gaps);
+        }
+        Ok(gaps)
+    }
+
+    // Appending to the log has to be serialized per run since where a chunk goes depends on how long the log
+    // currently is. Same idea as Job::prune_lock(). The writer also keeps the run's files open between chunks so a
+    // chatty job doesn't cost us an open() or two for every chunk of output.
+    async fn log_writer(&self) -> std::sync::Arc<tokio::sync::Mutex<LogWriter>> {
+        let mut writers = self.job.db.log_writers.lock().await;
+        writers.entry(self.run_db_id).or_default().clone()
+    }
+
+    // Run ids get reused once the newest run is deleted, so this has to happen whenever a run ends or goes away or the
+    // next run with the id would end up with this one's (possibly deleted) files.
+    async fn close_log_writer(&self) {
+        self.job.db.log_writers.lock().await.remove(&self.run_db_id); // Closes the files
+    }
+
+    async fn redactor<'a>(&self, writer: &'a mut LogWriter) -> Result<&'a Redactor, Box<dyn Error>> {
+        if writer.redactor.is_none() {
+            writer.redactor = Some(Redactor::new(&Settings::load(&self.job.db).await?.redaction, &[])?);
+        }
+        Ok(writer.redactor.as_ref().unwrap())
+    }
 
-        let bytes = chunk.as_bytes();
-        let mut log_file = File::options().create(true).append(true).open(&self.log_path()).await.map_err(|e| wrap(&e, &format!("open {}", self.log_path().to_string_lossy())))?;
-        log_file.write_all(bytes).await.map_err(|e| wrap(&e, &format!("write {}", self.log_path().to_string_lossy())))?;
+    // Output is stored exactly as the job wrote it, whether or not it's UTF-8 (other than masking).
+    async fn append_log(&self, writer: &mut LogWriter, stream: serve::OutKind, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.redactor(writer).await?;
+        if writer.log.is_none() {
+            self.mkdir_p().await.map_err(|e| wrap(&*e, "add_stdout"))?;
+            writer.log = Some(File::options().create(true).append(true).open(&self.log_path()).await.map_err(|e| wrap(&e, &format!("open {}", self.log_path().to_string_lossy())))?);
+            writer.held = self.unsettled_log(writer.redactor.as_ref().unwrap()).await?; // The server restarted in the middle of a line
+        }
+        if writer.stream != Some(stream) {
+            self.send_held(writer).await;
+            self.switch_stream(stream).await?;
+            writer.stream = Some(stream);
+        }
 
-        self.update_progress(bytes.len()).await?;
-        self.job.db.broker.send_log_append(&self, chunk).await;
+        // Spooled runs and older clients send their output in fixed size chunks, so a secret can get split between
+        // two of them. Mask the chunk along with the end of the log that's still unsettled, and if that turns up a
+        // secret that's partly in the log already, mask that part where it sits (the log's length stays the same).
+        let redactor = writer.redactor.as_ref().unwrap();
+        let held = writer.held.len();
+        let mut masked = writer.held.clone();
+        masked.extend_from_slice(bytes);
+        redactor.mask(&mut masked);
+        if masked[..held] != writer.held[..] {
+            self.overwrite_log(self.log_len() - held as u64, &masked[..held]).await?;
+        }
+        let settled = redactor.settled(&masked);
+        let log_file = writer.log.as_mut().unwrap();
+        log_file.write_all(&masked[held..]).await.map_err(|e| wrap(&e, &format!("write {}", self.log_path().to_string_lossy())))?;
+        log_file.flush().await.map_err(|e| wrap(&e, &format!("write {}", self.log_path().to_string_lossy())))?; // So log_len() sees it
+
+        self.update_progress(writer, bytes.len()).await?;
+
+        // Unsettled output doesn't go out to the event stream until it's done changing.
+        writer.held = masked.split_off(settled);
+        self.send_log_append(writer, stream, masked).await;
         self.job.db.broker.send_run_update_log_len(&self, self.log_len()).await;
         Ok(())
     }
 
-    pub async fn update_progress(&self, bytes: usize) -> Result<(), Box<dyn Error>> {
+    async fn send_log_append(&self, writer: &mut LogWriter, stream: serve::OutKind, bytes: Vec<u8>) {
+        // Events are json, so they get a lossy version. Hold back a multibyte character that got split between chunks
+        // until the rest of it shows up so it doesn't come out as garbage.
+        let mut text = std::mem::take(&mut writer.partial);
+        text.extend_from_slice(&bytes);
+        writer.partial = text.split_off(text.len() - crate::maybe_utf8::incomplete_tail(&text));
+        if text.is_empty() { return }
+        self.job.db.broker.send_log_append(&self, stream, &String::from_utf8_lossy(&text)).await;
+    }
+
+    // Lets go of the output append_log() was holding back from the event stream. Nothing more is coming (or it's
+    // coming from the other stream).
+    async fn send_held(&self, writer: &mut LogWriter) {
+        let held = std::mem::take(&mut writer.held);
+        if let Some(stream) = writer.stream { self.send_log_append(writer, stream, held).await }
+    }
+
+    // The end of the log that more output could still change the masking of. See Redactor::settled().
+    async fn unsettled_log(&self, redactor: &Redactor) -> Result<Vec<u8>, Box<dyn Error>> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+        let Some(mut log) = self.log_file().await? else { return Ok(vec![]) };
+        let len = self.log_len();
+        log.seek(std::io::SeekFrom::Start(len.saturating_sub(crate::redact::MAX_HELD as u64))).await?;
+        let mut end = vec![];
+        log.read_to_end(&mut end).await.map_err(|e| wrap(&e, &format!("read {}", self.log_path().to_string_lossy())))?;
+        Ok(end.split_off(redactor.settled(&end)))
+    }
+
+    async fn overwrite_log(&self, at: u64, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        use tokio::io::AsyncSeekExt;
+        let mut log = File::options().write(true).open(&self.log_path()).await.map_err(|e| wrap(&e, &format!("open {}", self.log_path().to_string_lossy())))?;
+        log.seek(std::io::SeekFrom::Start(at)).await?;
+        log.write_all(bytes).await.map_err(|e| wrap(&e, &format!("write {}", self.log_path().to_string_lossy())))?;
+        Ok(())
+    }
+
+    // Both streams go into the one log file so they stay interleaved the way they arrived. Which parts came from which
+    // stream is kept in a file called "streams" next to the log. Each line is a json StreamSwitch--everything from
+    // that offset on (until the next switch) came from that stream. Logs from before we kept track are all stdout.
+    fn streams_path(&self) -> PathBuf { self.log_path().with_file_name("streams") }
+    fn artifacts_path(&self) -> PathBuf { self.log_path().with_file_name("artifacts") }
+    fn summary_path(&self) -> PathBuf { self.log_path().with_file_name("summary.md") }
+
+    // The Markdown the job wrote to $SYNCRON_SUMMARY. It gets the same masking as the log.
+    pub async fn set_summary(&self, summary: &str) -> Result<(), Box<dyn Error>> {
+        let mut masked = summary.as_bytes().to_vec();
+        let writer = self.log_writer().await;
+        let mut writer = writer.lock().await;
+        self.redactor(&mut writer).await?.mask(&mut masked);
+        self.mkdir_p().await?;
+        tokio::fs::write(self.summary_path(), masked).await.map_err(|e| wrap(&e, &format!("write {}", self.summary_path().to_string_lossy())))?;
+        Ok(())
+    }
+
+    pub async fn summary(&self) -> Result<Option<String>, Box<dyn Error>> {
+        match read(self.summary_path()).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(wrap(&e, &format!("read {}", self.summary_path().to_string_lossy())))?,
+            Ok(summary) => Ok(Some(String::from_utf8_lossy(&summary).into_owned())),
+        }
+    }
+
+    // Where to put the artifact called `name`. Makes the directories it needs.
+    pub async fn new_artifact_path(&self, name: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        if name.as_os_str().is_empty() || !name.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+            Err(format!("Bad artifact name {:?}", name))?
+        }
+        let path = self.artifacts_path().join(name);
+        let dir = path.parent().expect("always at least in the artifacts dir");
+        DirBuilder::new().recursive(true).create(dir).await.map_err(|e| wrap(&e, &format!("mkdir -p {}", dir.to_string_lossy())))?;
+        Ok(path)
+    }
+
+    pub fn artifact_path(&self, name: &Path) -> Option<PathBuf> {
+        if !name.components().all(|c| matches!(c, std::path::Component::Normal(_))) { return None }
+        Some(self.artifacts_path().join(name)).filter(|p| p.is_file())
+    }
+
+    pub fn artifacts(&self) -> Result<Vec<Artifact>, Box<dyn Error>> {
+        list_artifacts(&self.artifacts_path())
+    }
+
+    // How much room the run takes up on disk: the log plus any artifacts and summary.
+    pub fn size(&self) -> u64 {
+        self.log_len() + self.artifacts().map(|a| a.iter().map(|a| a.size).sum()).unwrap_or(0)
+                       + self.summary_path().metadata().map(|m| m.len()).unwrap_or(0)
+    }
+
+    async fn switch_stream(&self, stream: serve::OutKind) -> Result<(), Box<dyn Error>> {
+        let switch = serde_json::to_string(&StreamSwitch { offset: self.log_len(), stream })? + "\n";
+        let streams_path = self.streams_path();
+        let mut file = File::options().create(true).append(true).open(&streams_path).await.map_err(|e| wrap(&e, &format!("open {}", streams_path.to_string_lossy())))?;
+        file.write_all(switch.as_bytes()).await.map_err(|e| wrap(&e, &format!("write {}", streams_path.to_string_lossy())))?;
+        Ok(())
+    }
+
+    // The parts of the log that came from `stream`, as (start, end) offsets.
+    async fn stream_ranges(&self, stream: serve::OutKind) -> Result<Vec<(u64, u64)>, Box<dyn Error>> {
+        let mut switches = vec![StreamSwitch { offset: 0, stream: serve::OutKind::Stdout }];
+        match read(self.streams_path()).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => Err(wrap(&e, &format!("read {}", self.streams_path().to_string_lossy())))?,
+            Ok(bytes) => for line in String::from_utf8(bytes)?.lines() {
+                switches.push(serde_json::from_str(line)?);
+            },
+        }
+        let len = self.log_len();
+        let mut ranges: Vec<(u64, u64)> = vec![];
+        for (i, switch) in switches.iter().enumerate() {
+            if switch.stream != stream { continue }
+            let end = switches.get(i+1).map(|next| next.offset).unwrap_or(len).min(len);
+            if end <= switch.offset { continue }
+            match ranges.last_mut() {
+                Some(last) if last.1 == switch.offset => last.1 = end,
+                _ => ranges.push((switch.offset, end)),
+            }
+        }
+        Ok(ranges)
+    }
+
+    pub async fn stream_len(&self, stream: serve::OutKind) -> Result<u64, Box<dyn Error>> {
+        Ok(self.stream_ranges(stream).await?.iter().map(|(start, end)| end - start).sum())
+    }
+
+    // Like reading the log but only the parts that came from `stream`. `seek` and `limit` work like they do for
+    // serve::apply_limit(), but on just that stream's output. Returns the total length of the stream's output
+    // along with what was read.
+    pub async fn read_stream(&self, stream: serve::OutKind, seek: Option<u64>, limit: Option<i64>) -> Result<(u64, Vec<u8>), Box<dyn Error>> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+        let ranges = self.stream_ranges(stream).await?;
+        let total = ranges.iter().map(|(start, end)| end - start).sum();
+        let (seek, len) = serve::apply_limit(total, seek, limit);
+        let Some(mut log) = self.log_file().await? else { return Ok((0, vec![])) };
+        let mut out = Vec::with_capacity(len as usize);
+        let mut pos = 0; // Where we are in the stream's output
+        for (start, end) in ranges {
+            if out.len() as u64 >= len { break }
+            let skip = seek.saturating_sub(pos);
+            pos += end - start;
+            if skip >= end - start { continue }
+            log.seek(std::io::SeekFrom::Start(start + skip)).await?;
+            (&mut log).take((end - start - skip).min(len - out.len() as u64)).read_to_end(&mut out).await?;
+        }
+        Ok((total, out))
+    }
+
+    async fn update_progress(&self, writer: &mut LogWriter, bytes: usize) -> Result<(), Box<dyn Error>> {
         // This is the first step of progress calculation. We keep track of when the client sends up stdout in
         // a file next to the log file called "progress". Each line is a json entry--we append a timestamp and
         // the number of bytes they sent. This is used to compute the progress on the next run, not the
@@ -593,8 +1382,12 @@ impl Run {
         let prog = ProgressChunk { timestamp_ms: chrono::Local::now().timestamp_millis(), bytes: bytes };
         let progress_str = serde_json::to_string(&prog)? + "\n";
         let progress_path = self.log_path().with_file_name("progress");
-        let mut file = File::options().create(true).append(true).open(&progress_path).await.map_err(|e| wrap(&e, &format!("open {}", progress_path.to_string_lossy())))?;
+        if writer.progress.is_none() {
+            writer.progress = Some(File::options().create(true).append(true).open(&progress_path).await.map_err(|e| wrap(&e, &format!("open {}", progress_path.to_string_lossy())))?);
+        }
+        let file = writer.progress.as_mut().unwrap();
         file.write_all(progress_str.as_bytes()).await.map_err(|e| wrap(&e, &format!("write {}", progress_path.to_string_lossy())))?;
+        file.flush().await.map_err(|e| wrap(&e, &format!("write {}", progress_path.to_string_lossy())))?;
 
         Ok(())
     }
@@ -624,6 +1417,9 @@ impl Run {
 
         let progress: Vec<ProgressChunk> = progress_str.trim().split('\n').map(|line| serde_json::from_str(line)).collect::<Result<_, _>>()?;
         if progress.len() == 0 { return Ok(()) }
+        // Output that showed up after the run ended was replayed from the client's spool. Its timing has nothing
+        // to do with how the job actually ran, so it would just poison the estimates for the next run.
+        if progress.iter().any(|p| p.timestamp_ms > end_timestamp_ms) { return Ok(()) }
         let start_timestamp_ms = self.date.timestamp_millis();
         let total_ms = (end_timestamp_ms - start_timestamp_ms) as f64;
         let total_bytes = progress.iter().rfold(0usize, |sum, &p| sum + p.bytes) as f64;
@@ -652,6 +1448,23 @@ impl Run {
     }
 
     #[tracing::instrument(skip(self),ret)]
+    // Ask the client to stop the job. It finds out in its next heartbeat.
+    pub async fn supersede(&self) -> Result<(), Box<dyn Error>> {
+        info!("Run [{}] {}/{}/{} is being superseded", self.run_db_id, self.job.user, self.job.name, self.run_id);
+        sqlx::query!("UPDATE run SET superseded = true WHERE run_id = ?", self.run_db_id).execute(self.job.db.sql()).await?;
+        Ok(())
+    }
+
+    pub async fn superseded(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(sqlx::query!("SELECT superseded FROM run WHERE run_id = ?", self.run_db_id).fetch_one(self.job.db.sql()).await?.superseded.unwrap_or(0) != 0)
+    }
+
+    // When the run was supposed to start, if the client waited a bit before actually starting it.
+    pub async fn set_scheduled(&self, scheduled_timestamp_ms: i64) -> Result<(), Box<dyn Error>> {
+        sqlx::query!("UPDATE run SET scheduled = ? WHERE run_id = ?", scheduled_timestamp_ms, self.run_db_id).execute(self.job.db.sql()).await?;
+        Ok(())
+    }
+
     pub async fn set_heartbeat(&self) -> Result<(), Box<dyn Error>> {
         let heartbeat = Some(chrono::Local::now().timestamp_millis());
         info!("Run [{}] {}/{}/{} Set heartbeat: {:?}", self.run_db_id, self.job.user, self.job.name, self.run_id, heartbeat);
@@ -664,23 +1477,29 @@ impl Run {
         sqlx::query!("SELECT heartbeat FROM run WHERE run_id = ?", self.run_db_id).fetch_one(self.job.db.sql()).await?.heartbeat.ok_or("Missing hearbeat".into())
     }
 
-    #[tracing::instrument(skip(self),ret)]
     pub async fn complete(&self, status: ExitStatus) -> Result<(), Box<dyn Error>> {
-        let end = Some(chrono::Local::now().timestamp_millis());
+        self.complete_at(status, chrono::Local::now().timestamp_millis(), None, None).await
+    }
+
+    // `sent` is the total number of bytes of output the client sent, if it knows.
+    #[tracing::instrument(skip(self),ret)]
+    pub async fn complete_at(&self, status: ExitStatus, end_timestamp_ms: i64, sent: Option<u64>, usage: Option<Usage>) -> Result<(), Box<dyn Error>> {
+        let end = Some(end_timestamp_ms.max(self.date.timestamp_millis()));
         let status_json = Some(serde_json::to_string(&status)?);
-        let success = match status {
-            ExitStatus::Exited(0) => true,
-            // If it didn't print anything but stil exited with non-zero status, then consider it success. This doesn't
-            // seem strictly correct, but cron doesn't care about exit status and so a lot of cron jobs return false
-            // (especially conditional ones).
-            ExitStatus::Exited(_) if self.log_len() == 0 => true,
-            _ => false,
-        };
+        let usage_json = match usage { Some(usage) => Some(serde_json::to_string(&usage)?), None => None };
+        let log_gaps = self.fill_log_gaps(sent).await?;
+        self.send_held(&mut *self.log_writer().await.lock().await).await;
+        self.close_log_writer().await;
+        let log_gaps_json = match log_gaps.len() { 0 => None, _ => Some(serde_json::to_string(&log_gaps)?) };
+        let (outcome, log_matches) = self.job.judge(status, &self.log_path()).await;
+        let log_matches_json = match log_matches.len() { 0 => None, _ => Some(serde_json::to_string(&log_matches)?) };
+        let (success, outcome_str) = (outcome != Outcome::Failure, outcome.as_str());
         trace!("Completing {}/{}/{} with {:?}", self.job.user, self.job.name, self.run_id, status);
-        sqlx::query!("UPDATE run SET status = ?, success = ?, end = ?, client_id = NULL WHERE run_id = ?", status_json, success, end, self.run_db_id).execute(self.job.db.sql()).await?;
+        sqlx::query!("UPDATE run SET status = ?, success = ?, outcome = ?, end = ?, log_gaps = ?, log_matches = ?, usage = ?, client_id = NULL WHERE run_id = ?",
+                     status_json, success, outcome_str, end, log_gaps_json, log_matches_json, usage_json, self.run_db_id).execute(self.job.db.sql()).await?;
         self.complete_progress(end.unwrap()).await?;
 
-        self.job.db.broker.send_run_update(&self, Some(status)).await;
+        self.job.db.broker.send_run_update(&self, Some(status), Some(outcome)).await;
 
         match self.job.prune_lock(false).await {
             Ok(Some((stats, pruned))) if pruned.len() > 0 => { for p in pruned.iter() { info!("{}/{}: pruned {} ({:>5}): {}", self.job.user, self.job.name, p.run_id, crate::human_bytes(p.size), p.reason) }
@@ -695,7 +1514,47 @@ impl Run {
         Ok(())
     }
 
-    pub fn progress(&self) -> Result<Option<serve::Progress>, Box<dyn Error>> {
+    pub async fn progress_report(&self) -> Result<Option<ProgressReport>, Box<dyn Error>> {
+        let report = sqlx::query!("SELECT progress_report FROM run WHERE run_id = ?", self.run_db_id).fetch_one(self.job.db.sql()).await?.progress_report;
+        Ok(match report { Some(r) => Some(serde_json::from_str(&r)?), None => None })
+    }
+
+    pub async fn set_progress_report(&self, report: &ProgressReport) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_string(report)?;
+        sqlx::query!("UPDATE run SET progress_report = ? WHERE run_id = ?", json, self.run_db_id).execute(self.job.db.sql()).await?;
+        self.job.db.broker.send_run_update_progress(self).await;
+        Ok(())
+    }
+
+    pub async fn set_metric(&self, name: &str, value: f64) -> Result<(), Box<dyn Error>> {
+        let mut transaction = self.job.db.sql().begin().await?;
+        let metrics = sqlx::query!("SELECT metrics FROM run WHERE run_id = ?", self.run_db_id).fetch_one(&mut *transaction).await?.metrics;
+        let mut metrics: Metrics = match metrics { Some(m) => serde_json::from_str(&m)?, None => Metrics::new() };
+        metrics.insert(name.to_string(), value);
+        let json = serde_json::to_string(&metrics)?;
+        sqlx::query!("UPDATE run SET metrics = ? WHERE run_id = ?", json, self.run_db_id).execute(&mut *transaction).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    // Uses what the job reported if it reported anything, otherwise it's a guess based on the previous run.
+    pub async fn progress(&self) -> Result<Option<serve::Progress>, Box<dyn Error>> {
+        let Some(report) = self.progress_report().await? else { return self.estimated_progress() };
+        let estimated = match report.done { None => self.estimated_progress()?, Some(_) => None }; // Only a stage, so we still need a guess
+        let percent = match (report.done, report.total) {
+            (Some(done), Some(total)) if total > 0.0 => Some((done / total).clamp(0.0, 1.0)),
+            _                                        => None,
+        };
+        let elapsed_ms = (chrono::Local::now().timestamp_millis() - self.date.timestamp_millis()) as f64;
+        Ok(Some(serve::Progress {
+            percent:     percent.map(|p| p as f32).or(estimated.as_ref().and_then(|e| e.percent)),
+            eta_seconds: percent.filter(|p| *p > 0.0).map(|p| (elapsed_ms * (1.0 - p) / p / 1000.0) as u32).or(estimated.and_then(|e| e.eta_seconds)),
+            stage:       report.stage,
+            done:        report.done.filter(|_| percent.is_none()),
+        }))
+    }
+
+    fn estimated_progress(&self) -> Result<Option<serve::Progress>, Box<dyn Error>> {
         let bytes = self.log_len() as usize;
         let elapsed_ms = chrono::Local::now().timestamp_millis() - self.date.timestamp_millis();
 
@@ -740,8 +1599,9 @@ impl Run {
             };
             if ave_percent > 1.0 { return Ok(None) } // clearly we have no idea
             debug!("byte_percent = {:?}%, time_percent = {:?}%, ave = {}%", byte_percent.map(|p| p * 100.0), time_percent.map(|p| p * 100.0), ave_percent * 100.0);
-            return Ok(Some(serve::Progress { percent: ave_percent as f32,
-                                             eta_seconds: (last_total_ms * (1.0 - ave_percent) / 1000.0) as u32 }));
+            return Ok(Some(serve::Progress { percent: Some(ave_percent as f32),
+                                             eta_seconds: Some((last_total_ms * (1.0 - ave_percent) / 1000.0) as u32),
+                                             stage: None, done: None }));
         }
 
         Ok(None)
@@ -757,8 +1617,25 @@ impl Run {
     }
 
     pub async fn delete(&self, reason: &str) -> Result<(), Box<dyn Error>> {
+        self.close_log_writer().await; // It might not have finished
+        if let Err(e) = tokio::fs::remove_dir_all(self.artifacts_path()).await {
+            if e.kind() != std::io::ErrorKind::NotFound { Err(wrap(&e, &format!("rm -rf {}", self.artifacts_path().to_string_lossy())))? }
+        }
+        if let Err(e) = remove_file(self.summary_path()).await {
+            if e.kind() != std::io::ErrorKind::NotFound { Err(e)? }
+        }
+        // Runs that never finished can still have these around
+        if let Err(e) = remove_file(self.log_path().with_file_name("progress")).await {
+            if e.kind() != std::io::ErrorKind::NotFound { Err(e)? }
+        }
+        if let Err(e) = tokio::fs::remove_dir_all(self.pending_path()).await {
+            if e.kind() != std::io::ErrorKind::NotFound { Err(wrap(&e, &format!("rm -rf {}", self.pending_path().to_string_lossy())))? }
+        }
         let path = self.log_path();
         if path.is_file() {
+            if let Err(e) = remove_file(self.streams_path()).await {
+                if e.kind() != std::io::ErrorKind::NotFound { Err(e)? }
+            }
             remove_file(&path).await?;
             // Because we nest log dirs to keep direntry counts down ("2024/8/9/2024-08-09T00:00:01.384-07:00/log"),
             // after we've deleted the log file try to delete parent directories until we can't any more.
@@ -776,6 +1653,75 @@ impl Run {
     }
 }
 
+const MAX_LOG_MATCHES: usize = 100;  // Of each level. The rest still count, they just don't get listed.
+const MAX_LOG_MATCH_TEXT: usize = 200;
+
+fn compile_log_rules(rules: &[LogRule]) -> Result<Vec<(regex::bytes::Regex, LogLevel)>, Box<dyn Error>> {
+    rules.iter().map(|rule| Ok((regex::bytes::Regex::new(&rule.pattern).map_err(|e| crate::wrap_str(e, &format!("Bad log rule {:?}", rule.pattern)))?, rule.level)))
+                .collect()
+}
+
+// Goes through the log a line at a time looking for lines that match `rules`. The worst level wins when a line
+// matches more than one.
+async fn scan_log(log_path: &Path, rules: &[LogRule]) -> Result<Vec<LogMatch>, Box<dyn Error>> {
+    use tokio::io::AsyncBufReadExt;
+    if rules.is_empty() { return Ok(vec![]) }
+    let rules = compile_log_rules(rules)?;
+    let log = match File::open(log_path).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        log => log.map_err(|e| wrap(&e, &format!("open {}", log_path.to_string_lossy())))?,
+    };
+    let mut log = tokio::io::BufReader::new(log);
+    let (mut matches, mut warnings, mut failures) = (vec![], 0, 0);
+    let (mut line, mut number, mut offset) = (vec![], 0, 0);
+    loop {
+        line.clear();
+        let read = log.read_until(b'\n', &mut line).await.map_err(|e| wrap(&e, &format!("read {}", log_path.to_string_lossy())))?;
+        if read == 0 { break }
+        number += 1;
+        if let Some(level) = rules.iter().filter(|(re, _)| re.is_match(&line)).map(|(_, level)| *level).max() {
+            let count = match level { LogLevel::Warning => &mut warnings, LogLevel::Failure => &mut failures };
+            if *count < MAX_LOG_MATCHES {
+                let text = String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(&line));
+                matches.push(LogMatch { line: number, offset, level, text: text.chars().take(MAX_LOG_MATCH_TEXT).collect() });
+            }
+            *count += 1;
+        }
+        offset += read as u64;
+    }
+    Ok(matches)
+}
+
+// Open files for a run that's still getting output. See Run::log_writer().
+#[derive(Debug, Default)]
+struct LogWriter {
+    log:      Option<File>,
+    progress: Option<File>,
+    stream:   Option<serve::OutKind>, // Stream of the last chunk written
+    partial:  Vec<u8>,                // Start of a UTF-8 character that hasn't been sent to the event stream yet
+    redactor: Option<Redactor>,       // Set up by Run::insert(), or from the settings if the server restarted since then
+    held:     Vec<u8>,                // End of the log that hasn't settled yet (see Run::append_log())
+}
+
+// Lines of the "streams" file in the run dir. See Run::streams_path().
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+struct StreamSwitch {
+    offset: u64,
+    stream: serve::OutKind,
+}
+
+#[derive(Debug)]
+pub enum Start {
+    Run(Run),
+    Skip(Run), // Already completed with ExitStatus::Skipped
+    Wait(Job), // Nothing was created--try again later
+}
+
+static START_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+// run_db_id -> LogWriter. Per Db since run ids are only unique within a database.
+type LogWriters = std::sync::Arc<tokio::sync::Mutex<std::collections::HashMap<i64,std::sync::Arc<tokio::sync::Mutex<LogWriter>>>>>;
+
 pub fn time_from_timestamp_ms(timestamp_ms: i64) -> chrono::DateTime<chrono::Local> {
     use chrono::TimeZone;
     chrono::Local.timestamp_millis_opt(timestamp_ms).earliest().expect("bad timestamp").into()
@@ -790,6 +1736,7 @@ pub struct Settings {
     #[allow(dead_code)]
     pub db: Db,
     pub retention: RetentionSettings,
+    pub redaction: RedactionRules,
 }
 
 impl Settings {
@@ -801,6 +1748,11 @@ impl Settings {
                     serde_sqlite_jsonb::from_reader(&*rows.retention).unwrap_or(RetentionSettings::default())
                 } else { RetentionSettings::default() }
             },
+            redaction: {
+                if let Some(rows) = sqlx::query!("SELECT value AS redaction FROM settings WHERE key = 'redaction'").fetch_optional(db.sql()).await? {
+                    serde_sqlite_jsonb::from_reader(&*rows.redaction).unwrap_or_default()
+                } else { RedactionRules::default() }
+            },
         })
     }
 
@@ -811,4 +1763,13 @@ impl Settings {
         self.retention = new_retention;
         Ok(())
     }
+
+    pub async fn set_redaction(&mut self, new_redaction: RedactionRules) -> Result<(), Box<dyn Error>> {
+        Redactor::new(&new_redaction, &[])?; // Don't save rules that won't compile
+        let json = serde_json::to_string(&new_redaction)?;
+        sqlx::query!("INSERT INTO settings (key, value) VALUES ('redaction', jsonb(?))
+                        ON CONFLICT (key) DO UPDATE SET value=excluded.value", json) .execute(self.db.sql()).await?;
+        self.redaction = new_redaction;
+        Ok(())
+    }
 }
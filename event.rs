@@ -5,7 +5,7 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, Mutex};
 
-use crate::{db, serve::{JobInfo, Progress, RunInfo}};
+use crate::{db, serve::{JobInfo, OutKind, Progress, RunInfo}};
 
 // Events have a "topic" that subscribers can subscribe to. If this sounds like
 // MQTT, it's because it was the inspiration. When you subscribe you give a
@@ -21,6 +21,16 @@ pub struct Event {
     detail: EventDetail,
 }
 
+impl Event {
+    // The syncron user whose job the event is about, so people only get events for jobs they're allowed to see.
+    pub fn user(&self) -> Option<&str> {
+        match self.detail {
+            EventDetail::JobCreate(ref job) => Some(&job.user),
+            _                               => self.topic.strip_prefix("job/").and_then(|t| t.split('/').next()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum EventDetail {
@@ -32,8 +42,9 @@ pub enum EventDetail {
     RunUpdateLogLen(u64),
     RunUpdateProgress(Progress),
     RunDelete { reason: String },
-    RunLogAppend { chunk: String },
+    RunLogAppend { chunk: String, stream: OutKind },
     PruneProgress { total: usize, current: db::PruneStats },
+    DeleteProgress { total: usize, current: db::RunCount },
 }
 
 #[derive(Clone, Debug)]
@@ -87,9 +98,10 @@ impl Broker {
         self.send(Event { topic: format!("job/{}/{}/run/{}", run.job.user, run.job.id, run.run_id), detail }).await;
     }
 
-    pub async fn send_run_update(&self, run: &db::Run, status: Option<db::ExitStatus>) {
+    pub async fn send_run_update(&self, run: &db::Run, status: Option<db::ExitStatus>, outcome: Option<db::Outcome>) {
         let mut ri: RunInfo = run.into();
         ri.status = status;
+        ri.outcome = outcome;
         let detail = EventDetail::RunUpdate(ri);
         if run.is_latest().await.unwrap_or(false) {
             self.send(Event { topic: format!("job/{}/{}/latest", run.job.user, run.job.id), detail: detail.clone() }).await;
@@ -105,8 +117,8 @@ impl Broker {
         self.send(Event { topic: format!("job/{}/{}/run/{}", run.job.user, run.job.id, run.run_id), detail }).await;
     }
 
-    pub async fn send_log_append(&self, run: &db::Run, chunk: &str) {
-        let detail = EventDetail::RunLogAppend { chunk: chunk.to_owned() };
+    pub async fn send_log_append(&self, run: &db::Run, stream: OutKind, chunk: &str) {
+        let detail = EventDetail::RunLogAppend { chunk: chunk.to_owned(), stream };
         if run.is_latest().await.unwrap_or(false) {
             self.send(Event { detail: detail.clone(), topic: format!("job/{}/{}/latest/log", run.job.user, run.job.id) }).await;
         }
@@ -122,7 +134,7 @@ impl Broker {
     }
 
     pub async fn send_run_update_progress(&self, run: &db::Run) {
-        let Ok(Some(progress)) = run.progress() else { return };
+        let Ok(Some(progress)) = run.progress().await else { return };
         let detail: EventDetail = EventDetail::RunUpdateProgress(progress);
         if run.is_latest().await.unwrap_or(false) {
             self.send(Event { detail: detail.clone(), topic: format!("job/{}/{}/latest", run.job.user, run.job.id) }).await;
@@ -130,6 +142,11 @@ impl Broker {
         self.send(Event { detail, topic: format!("job/{}/{}/run/{}", run.job.user, run.job.id, run.run_id) }).await;
     }
 
+    pub async fn send_delete_progress(&self, job: &db::Job, purged: &db::RunCount, runs: usize) {
+        let detail: EventDetail = EventDetail::DeleteProgress { total: runs, current: *purged };
+        self.send(Event { detail, topic: format!("job/{}/{}/delete", job.user, job.id) }).await;
+    }
+
     pub async fn send_prune_progress(&self, job: &db::Job, stats: &db::PruneStats, runs: usize) {
         let detail: EventDetail = EventDetail::PruneProgress { total: runs, current: stats.clone() };
         self.send(Event { detail, topic: format!("job/{}/{}/prune", job.user, job.id) }).await;
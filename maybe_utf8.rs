@@ -27,3 +27,15 @@ impl From<MaybeUTF8> for OsString {
         }
     }
 }
+
+// How many bytes at the end of `bytes` are the start of a UTF-8 character that got cut off. Doesn't care whether the
+// rest of `bytes` is valid.
+pub fn incomplete_tail(bytes: &[u8]) -> usize {
+    for i in 1..=3.min(bytes.len()) {
+        let b = bytes[bytes.len() - i];
+        if b & 0xc0 == 0x80 { continue } // Continuation byte, keep looking for the start
+        let len = match b { 0xc0..=0xdf => 2, 0xe0..=0xef => 3, 0xf0..=0xf7 => 4, _ => return 0 };
+        return if len > i { i } else { 0 };
+    }
+    0
+}
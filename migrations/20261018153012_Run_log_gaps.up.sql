@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN log_gaps TEXT; -- json array of LogGap
@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN usage TEXT; -- json Usage
@@ -0,0 +1 @@
+UPDATE run SET status = '"ClientTimeout"' WHERE json_extract(status, '$.ClientTimeout') IS NOT NULL;
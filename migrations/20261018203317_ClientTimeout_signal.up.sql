@@ -0,0 +1,2 @@
+-- ClientTimeout records the signal that killed the job now. Before, it was always SIGKILL.
+UPDATE run SET status = '{"ClientTimeout":9}' WHERE status = '"ClientTimeout"';
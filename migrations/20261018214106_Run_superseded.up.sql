@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN superseded INTEGER; -- A newer run asked this one to stop (Overlap::KillPrevious)
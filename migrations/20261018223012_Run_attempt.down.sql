@@ -0,0 +1,3 @@
+DROP INDEX run_attempt_of;
+ALTER TABLE run DROP COLUMN attempt;
+ALTER TABLE run DROP COLUMN attempt_of;
@@ -0,0 +1,5 @@
+-- Retries are runs of their own. attempt_of is the run_id of the first attempt (no REFERENCES--pruning can delete it
+-- out from under the later attempts). attempt counts from 2. Both are NULL on first attempts.
+ALTER TABLE run ADD COLUMN attempt_of INTEGER;
+ALTER TABLE run ADD COLUMN attempt INTEGER;
+CREATE INDEX run_attempt_of ON run (attempt_of);
@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN scheduled INTEGER; -- ms since the epoch. When the run was supposed to start, if the client waited (--splay)
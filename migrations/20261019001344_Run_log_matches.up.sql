@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN log_matches TEXT; -- json array of LogMatch. Lines of the log that matched the job's log rules
@@ -0,0 +1,5 @@
+ALTER TABLE run ADD COLUMN outcome TEXT; -- 'success', 'warning' or 'failure'. NULL until the run completes. `success` is kept as outcome != 'failure'
+UPDATE run SET outcome = CASE WHEN success = 0                                            THEN 'failure'
+                              WHEN success = 1 AND log_matches LIKE '%"level":"warning"%' THEN 'warning'
+                              WHEN success = 1                                            THEN 'success'
+                         END;
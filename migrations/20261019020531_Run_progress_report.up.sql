@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN progress_report TEXT; -- json ProgressReport. What the job said about its own progress over $SYNCRON_CONTROL
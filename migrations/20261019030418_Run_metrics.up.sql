@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN metrics TEXT; -- json object of metric name to value. Numbers the job reported (`syncron metric`)
@@ -0,0 +1,13 @@
+CREATE TABLE token (
+       token_id INTEGER PRIMARY KEY ASC NOT NULL,
+       name TEXT NOT NULL,
+       hash TEXT NOT NULL,     -- hex sha256 of the token. The token itself is only ever shown when it's created.
+       user TEXT,              -- Only good for runs of this user's jobs
+       namespace TEXT,         -- Only good for jobs whose ids start with this
+       created INTEGER NOT NULL,
+       last_used INTEGER,
+       revoked INTEGER,
+
+       UNIQUE(name),
+       UNIQUE(hash)
+) STRICT;
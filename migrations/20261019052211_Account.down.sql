@@ -0,0 +1,3 @@
+DROP TABLE session;
+DROP TABLE account_user;
+DROP TABLE account;
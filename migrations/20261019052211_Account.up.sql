@@ -0,0 +1,30 @@
+CREATE TABLE account (
+       account_id INTEGER PRIMARY KEY ASC NOT NULL,
+       name TEXT NOT NULL,
+       password TEXT NOT NULL,
+       role TEXT NOT NULL,
+       created INTEGER NOT NULL,
+       last_login INTEGER,
+
+       UNIQUE(name)
+) STRICT;
+
+-- Which syncron users' jobs an account can see. An account with no rows here can see everything.
+CREATE TABLE account_user (
+       account_id INTEGER NOT NULL,
+       user TEXT NOT NULL,
+
+       UNIQUE(account_id, user),
+       FOREIGN KEY (account_id) REFERENCES account (account_id) ON DELETE CASCADE
+) STRICT;
+
+CREATE TABLE session (
+       session_id INTEGER PRIMARY KEY ASC NOT NULL,
+       account_id INTEGER NOT NULL,
+       hash TEXT NOT NULL,
+       created INTEGER NOT NULL,
+       last_used INTEGER NOT NULL,
+
+       UNIQUE(hash),
+       FOREIGN KEY (account_id) REFERENCES account (account_id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1 @@
+ALTER TABLE job ADD COLUMN deleted INTEGER; -- When it was deleted. It's hidden, then purged for real once the undo window is up.
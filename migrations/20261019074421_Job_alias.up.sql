@@ -0,0 +1,11 @@
+-- Ids that jobs used to have before they were renamed or merged into another job. Clients still using an old id end
+-- up in job_id.
+CREATE TABLE job_alias (
+       user_id INTEGER NOT NULL,
+       id TEXT NOT NULL,
+       job_id INTEGER NOT NULL,
+
+       UNIQUE(user_id, id),
+       FOREIGN KEY (user_id) REFERENCES user (user_id),
+       FOREIGN KEY (job_id) REFERENCES job (job_id) ON DELETE CASCADE
+) STRICT;
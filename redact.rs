@@ -0,0 +1,174 @@
+// Copyright © 2026 David Caldwell <david@porkrind.org>
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+
+use regex::bytes::Regex;
+
+use crate::maybe_utf8::MaybeUTF8;
+
+// Cron jobs tend to carry credentials around in their environment, and the environment gets shown to anyone who can
+// see the dashboard. These rules keep them out of it. The client applies them before it sends anything, and the server
+// applies them again before it stores anything (older clients don't redact anything themselves, and spooled runs only
+// get the default rules since the client couldn't ask the server for its own).
+
+const REDACTED: &[u8] = b"[REDACTED]";
+const MIN_SECRET_LEN: usize = 4;  // Masking every "1" in the log would do more harm than good
+pub const MAX_HELD: usize = 64*1024;  // How long a line StreamMasker waits on before it gives up looking for the end
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct RedactionRules {
+    #[serde(default)]
+    pub names:  Vec<String>, // Globs (`*` and `?`, case insensitive). Variables with matching names get their whole value redacted.
+    #[serde(default)]
+    pub values: Vec<String>, // Regexes. Matches get redacted wherever they show up in a value.
+    #[serde(default)]
+    pub logs:   bool,        // Mask them in the job's output, too
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        RedactionRules { names:  ["*TOKEN*", "*SECRET*", "*PASSWORD*", "*PASSWD*", "*API_KEY*", "*PRIVATE_KEY*", "*CREDENTIAL*"].iter().map(|s| s.to_string()).collect(),
+                         values: vec![],
+                         logs:   false }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    names:   Vec<Regex>,
+    values:  Vec<Regex>,
+    logs:    bool,
+    secrets: Vec<Vec<u8>>, // Values of the variables that were redacted by name, so they can be masked in the log
+}
+
+fn glob_to_regex(glob: &str) -> Result<Regex, Box<dyn Error>> {
+    let pattern = glob.split('*').map(|part| part.split('?').map(regex::escape).collect::<Vec<_>>().join("."))
+                                 .collect::<Vec<_>>().join(".*");
+    Ok(Regex::new(&format!("(?is-u)^{}$", pattern))?)
+}
+
+fn bytes(s: &MaybeUTF8) -> Vec<u8> {
+    OsString::from(s.clone()).into_vec()
+}
+
+impl Redactor {
+    // `env` is the job's environment, before redaction.
+    pub fn new(rules: &RedactionRules, env: &[(MaybeUTF8, MaybeUTF8)]) -> Result<Redactor, Box<dyn Error>> {
+        // An empty regex would match everywhere. Nobody means that.
+        let names = rules.names.iter().filter(|glob| !glob.is_empty()).map(|glob| glob_to_regex(glob).map_err(|e| crate::wrap_str(&*e, &format!("Bad redaction glob {:?}", glob))))
+                                      .collect::<Result<Vec<_>, _>>()?;
+        let values = rules.values.iter().filter(|re| !re.is_empty()).map(|re| Regex::new(re).map_err(|e| crate::wrap_str(e, &format!("Bad redaction regex {:?}", re))))
+                                        .collect::<Result<Vec<_>, _>>()?;
+        let mut redactor = Redactor { names, values, logs: rules.logs, secrets: vec![] };
+        redactor.secrets = env.iter().filter(|(name, _)| redactor.name_matches(&bytes(name)))
+                                     .map(|(_, value)| bytes(value))
+                                     .filter(|value| value.len() >= MIN_SECRET_LEN && value != REDACTED)
+                                     .collect();
+        Ok(redactor)
+    }
+
+    fn name_matches(&self, name: &[u8]) -> bool {
+        self.names.iter().any(|glob| glob.is_match(name))
+    }
+
+    pub fn env(&self, env: &[(MaybeUTF8, MaybeUTF8)]) -> Vec<(MaybeUTF8, MaybeUTF8)> {
+        env.iter().map(|(name, value)| {
+            let value = if self.name_matches(&bytes(name)) { REDACTED.to_vec() }
+                        else { self.values.iter().fold(bytes(value), |value, re| re.replace_all(&value, REDACTED).into_owned()) };
+            (name.clone(), MaybeUTF8::new(OsString::from_vec(value)))
+        }).collect()
+    }
+
+    // How much of `bytes` (the start of some output) is safe to let go of, because nothing that comes after it can
+    // change how it gets masked: up to the end of the last full line. All of it if the logs aren't masked or the
+    // line has gone on for too long.
+    pub fn settled(&self, bytes: &[u8]) -> usize {
+        match bytes.len() {
+            len if !self.logs || len > MAX_HELD => len,
+            _ => bytes.iter().rposition(|b| *b == b'\n').map_or(0, |newline| newline + 1),
+        }
+    }
+
+    // Overwrites secrets in `bytes` with '*'s. The length doesn't change, so offsets into the log stay good.
+    pub fn mask(&self, bytes: &mut [u8]) {
+        if !self.logs { return }
+        let mut spans = vec![];
+        for secret in self.secrets.iter() {
+            spans.extend(bytes.windows(secret.len()).enumerate().filter(|(_, w)| w == secret).map(|(at, _)| (at, at + secret.len())));
+        }
+        for re in self.values.iter() {
+            spans.extend(re.find_iter(bytes).map(|m| (m.start(), m.end())));
+        }
+        for (start, end) in spans {
+            bytes[start..end].fill(b'*');
+        }
+    }
+}
+
+// Masks output as it streams by. The end of the last line gets held back until the rest of it shows up so a secret
+// that got split between two reads still gets caught.
+pub struct StreamMasker<'a> {
+    redactor: &'a Redactor,
+    held:     Vec<u8>,
+}
+
+impl<'a> StreamMasker<'a> {
+    pub fn new(redactor: &'a Redactor) -> StreamMasker<'a> {
+        StreamMasker { redactor, held: vec![] }
+    }
+
+    // Returns whatever is ready to go out. It may be empty.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if !self.redactor.logs { return chunk.to_vec() }
+        self.held.extend_from_slice(chunk);
+        let ready = self.redactor.settled(&self.held);
+        let mut out = self.held.drain(..ready).collect::<Vec<_>>();
+        self.redactor.mask(&mut out);
+        out
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut out = std::mem::take(&mut self.held);
+        self.redactor.mask(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(MaybeUTF8, MaybeUTF8)> {
+        vars.iter().map(|(k, v)| (MaybeUTF8::UTF8(k.to_string()), MaybeUTF8::UTF8(v.to_string()))).collect()
+    }
+
+    #[test]
+    fn test_env() {
+        let rules = RedactionRules { names: vec!["*token*".into(), "DB_PASS?".into()], values: vec!["ghp_[A-Za-z0-9]+".into()], logs: false };
+        let raw = env(&[("GITHUB_TOKEN", "abc123"), ("DB_PASSW", "hunter2"), ("DB_PASSWORD", "not-matched"),
+                        ("URL", "https://ghp_SeCrEt@github.com/"), ("HOME", "/root")]);
+        let redactor = Redactor::new(&rules, &raw).unwrap();
+        assert_eq!(redactor.env(&raw), env(&[("GITHUB_TOKEN", "[REDACTED]"), ("DB_PASSW", "[REDACTED]"), ("DB_PASSWORD", "not-matched"),
+                                             ("URL", "https://[REDACTED]@github.com/"), ("HOME", "/root")]));
+        assert!(Redactor::new(&RedactionRules { values: vec!["(".into()], ..Default::default() }, &[]).is_err());
+    }
+
+    #[test]
+    fn test_mask() {
+        let rules = RedactionRules { names: vec!["*TOKEN*".into()], values: vec!["ghp_[A-Za-z0-9]+".into()], logs: true };
+        let redactor = Redactor::new(&rules, &env(&[("API_TOKEN", "sekrit"), ("SHORT_TOKEN", "1"), ("OLD_TOKEN", "[REDACTED]")])).unwrap();
+        let mut log = b"token is sekrit, or ghp_abc. 1 [REDACTED]\n".to_vec();
+        redactor.mask(&mut log);
+        assert_eq!(log, b"token is ******, or *******. 1 [REDACTED]\n");
+
+        let mut masker = StreamMasker::new(&redactor);
+        let mut out = masker.push(b"first line\nthe token is sek");
+        assert_eq!(out, b"first line\n");
+        out.extend(masker.push(b"rit"));
+        out.extend(masker.push(b" and that's it"));
+        out.extend(masker.finish());
+        assert_eq!(out, b"first line\nthe token is ****** and that's it");
+    }
+}
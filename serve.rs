@@ -4,15 +4,17 @@ use std::error::Error;
 use std::io::Read;
 use std::path::{Path,PathBuf};
 
-use rocket::http::ContentType;
+use rocket::http::{ContentType, CookieJar, Status};
 use rocket::request::Request;
 use rocket::response::{Debug,Redirect, Responder, Response};
 use rocket::serde::{Serialize, Deserialize, json::Json};
 use rocket::State;
 
 use crate::{db, event};
+use crate::auth::{self, Admin, AnyAuth, ClientAuth, Operator, WebAuth};
 use crate::db::Db;
 use crate::maybe_utf8::MaybeUTF8;
+use crate::redact::RedactionRules;
 use crate::{wrap,wrap_str};
 
 type WebResult<T, E = Debug<Box<dyn Error>>> = std::result::Result<T, E>; // What is this magic??
@@ -65,6 +67,25 @@ fn utf8_or_bust(bytes: Vec<u8>, origin: &str) -> String {
     String::from_utf8(bytes).or_else::<(),_>(|e| Ok(format!("# UTF-8 error in {}: {}", origin, e))).unwrap()
 }
 
+fn markdown_options() -> comrak::ComrakOptions {
+    let mut options = comrak::ComrakOptions::default();
+    options.extension.header_ids = Some("".to_string());
+    options.extension.description_lists = true;
+    options.extension.tasklist = true;
+    options
+}
+
+// Job summaries ($SYNCRON_SUMMARY) get rendered like the docs, plus the GitHub extensions people will expect from CI
+// step summaries. Raw HTML in them is left out (comrak's default), so a job can't put script on the page.
+pub fn render_summary(md: &str) -> String {
+    let mut options = markdown_options();
+    options.extension.header_ids = Some("summary-".to_string()); // Don't collide with the ids on the rest of the page
+    options.extension.table = true;
+    options.extension.strikethrough = true;
+    options.extension.autolink = true;
+    comrak::markdown_to_html(md, &options)
+}
+
 #[get("/docs/<file..>")]
 #[tracing::instrument(name="GET /docs/<file..>")]
 async fn docs(file: PathBuf) -> Option<(ContentType, String)> {
@@ -78,10 +99,7 @@ async fn docs(file: PathBuf) -> Option<(ContentType, String)> {
     file_from_zip_or_fs(&Path::new("docs").join(file.with_extension("md")))
                                    .map(|(_,md)| {
                                        use comrak::{parse_document,format_html,markdown_to_html,Arena,ComrakOptions};
-                                       let mut options = ComrakOptions::default();
-                                       options.extension.header_ids = Some("".to_string());
-                                       options.extension.description_lists = true;
-                                       options.extension.tasklist = true;
+                                       let options = markdown_options();
 
                                        let arena = Arena::new();
                                        let root = parse_document(&arena, &md, &options);
@@ -113,29 +131,71 @@ async fn docs(file: PathBuf) -> Option<(ContentType, String)> {
 
 /////////////////////////////////// Client API ///////////////////////////////////
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateRunReq {
     pub user: String,
     pub name: String,
     pub id:   Option<String>,
     pub cmd:  String,
     pub env:  std::vec::Vec<(MaybeUTF8, MaybeUTF8)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>, // ms since the epoch. Only set when replaying a spooled run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempt_of: Option<String>, // run_id of the first attempt, when this is a retry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<i64>, // ms since the epoch. When the client got started, if it waited before starting the job (--splay).
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateRunResp {
-    pub id: String,
+    pub id: String,     // Empty if `action` is Wait
     pub job_id: String,
-    pub run_id: String,
+    pub run_id: String, // Empty if `action` is Wait
+    #[serde(default)]
+    pub action: StartAction,
+    #[serde(default)]
+    pub retry: db::RetrySettings, // The job's settings. The client decides whether to use them.
+    #[serde(default)]
+    pub success: db::SuccessPolicy, // So the client doesn't retry runs that the server won't call failures
+}
+
+// What the job's overlap setting (db::Overlap) says the client should do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartAction {
+    #[default]
+    Run,
+    Skip, // The run has already been recorded as skipped. Don't run the job.
+    Wait, // A previous run is still going. Try again in a bit.
 }
 
 #[post("/run/create", data="<req>")]
-#[tracing::instrument(name="POST /run/create", skip(db,req), fields(req.user=%&req.user,req.name=%&req.name,req.id=req.id.as_deref(),req.cmd=%&req.cmd), ret)]
-async fn run_create(db: &State<Db>, req: Json<CreateRunReq>) -> WebResult<Json<CreateRunResp>> {
-    let run = db::Run::create(db, &req.user, &req.name, req.id.as_deref(), req.cmd.clone(), req.env.clone()).await?;
-    Ok(Json(CreateRunResp { id:format!("{}", run.client_id.unwrap()), job_id: run.job.id, run_id: run.run_id }))
+#[tracing::instrument(name="POST /run/create", skip(db,auth,req), fields(req.user=%&req.user,req.name=%&req.name,req.id=req.id.as_deref(),req.cmd=%&req.cmd), ret)]
+async fn run_create(db: &State<Db>, auth: ClientAuth, req: Json<CreateRunReq>) -> WebResult<Json<CreateRunResp>> {
+    auth.check_new(db, &req.user, &req.id.clone().unwrap_or_else(|| db::slug(&req.name))).await?;
+    let start = match (req.start, &req.attempt_of) {
+        // There's no overlap to prevent for retries--the first attempt already got past it.
+        (_, Some(first)) => {
+            let job = db::Job::ensure(db, &req.user, &req.name, req.id.as_deref()).await?;
+            let first = job.run(first).await?;
+            db::Start::Run(first.retry(req.cmd.clone(), req.env.clone(), req.start).await?)
+        },
+        // Neither is there for spooled runs, they already happened.
+        (Some(_), None)  => db::Start::Run(db::Run::create(db, &req.user, &req.name, req.id.as_deref(), req.cmd.clone(), req.env.clone(), req.start).await?),
+        (None, None)     => db::Run::start(db, &req.user, &req.name, req.id.as_deref(), req.cmd.clone(), req.env.clone()).await?,
+    };
+    if let (Some(scheduled), db::Start::Run(run) | db::Start::Skip(run)) = (req.scheduled, &start) {
+        run.set_scheduled(scheduled).await?;
+    }
+    Ok(Json(match start {
+        db::Start::Run(run)  => CreateRunResp { id:format!("{}", run.client_id.unwrap()), retry: run.job.settings.retry, success: run.job.settings.success.clone(), job_id: run.job.id, run_id: run.run_id, action: StartAction::Run },
+        db::Start::Skip(run) => CreateRunResp { id:format!("{}", run.client_id.unwrap_or(0)), retry: run.job.settings.retry, success: run.job.settings.success.clone(), job_id: run.job.id, run_id: run.run_id, action: StartAction::Skip },
+        db::Start::Wait(job) => CreateRunResp { id:String::new(), retry: job.settings.retry, success: job.settings.success.clone(), job_id: job.id, run_id: String::new(), action: StartAction::Wait },
+    }))
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, FromFormField)]
+#[serde(rename_all = "lowercase")]
 pub enum OutKind {
     Stdout, Stderr
 }
@@ -146,52 +206,253 @@ impl std::fmt::Display for OutKind {
         Ok(())
     }
 }
+impl std::str::FromStr for OutKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s { "stdout" => Ok(OutKind::Stdout),
+                  "stderr" => Ok(OutKind::Stderr),
+                  _        => Err(format!("Bad output kind: {}", s)) }
+    }
+}
+
+// A chunk of output as it goes over the wire to /run/<id>/output (and as it sits in the client's spool): a
+// "<stdout|stderr> <seq> <offset> <length>\n" header followed by <length> bytes of raw output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputFrame {
+    pub kind:   OutKind,
+    pub seq:    u64,
+    pub offset: u64, // Where the chunk goes in the log
+    pub chunk:  Vec<u8>,
+}
+
+impl OutputFrame {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut frame = format!("{} {} {} {}\n", self.kind, self.seq, self.offset, self.chunk.len()).into_bytes();
+        frame.extend_from_slice(&self.chunk);
+        frame
+    }
+
+    pub fn end(&self) -> u64 { self.offset + self.chunk.len() as u64 }
+
+    // Returns None at the end of the stream. A frame that got cut off (the connection dropped, or the client died in
+    // the middle of writing it to the spool) also counts as the end--everything before it is still good.
+    pub async fn read<R: tokio::io::AsyncBufRead+Unpin>(from: &mut R) -> Result<Option<OutputFrame>, Box<dyn Error>> {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+        let mut header = vec![];
+        if from.read_until(b'\n', &mut header).await? == 0 { return Ok(None) }
+        if header.last() != Some(&b'\n') { return Ok(None) }
+        let header = String::from_utf8(header)?;
+        let [kind, seq, offset, len] = header.trim_end().split(' ').collect::<Vec<_>>()[..] else { Err(format!("Bad output frame header {:?}", header))? };
+        let mut chunk = vec![0; len.parse()?];
+        if let Err(e) = from.read_exact(&mut chunk).await {
+            if e.kind() == std::io::ErrorKind::UnexpectedEof { return Ok(None) }
+            Err(e)?
+        }
+        Ok(Some(OutputFrame { kind: kind.parse()?, seq: seq.parse()?, offset: offset.parse()?, chunk }))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutputResp {
+    pub received: u64, // We have everything the client sent up to this offset in the log
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HeartbeatResp {
+    #[serde(default)]
+    pub stop: bool, // A newer run wants this one stopped (db::Overlap::KillPrevious)
+}
 
 #[post("/run/<id>/heartbeat")]
-#[tracing::instrument(name="POST /run/<id>/heartbeat", skip(db), ret)]
-async fn run_heartbeat(db: &State<Db>, id: u128) -> WebResult<()> {
-    let run = db::Run::from_client_id(db, id).await?;
+#[tracing::instrument(name="POST /run/<id>/heartbeat", skip(db,auth), ret)]
+async fn run_heartbeat(db: &State<Db>, auth: ClientAuth, id: u128) -> WebResult<Json<HeartbeatResp>> {
+    let run = auth.run(db, id).await?;
     run.set_heartbeat().await?;
-    Ok(())
+    Ok(Json(HeartbeatResp { stop: run.superseded().await? }))
+}
+
+
+fn short_data(data: &[u8]) -> String {
+    format!("[{}]{}...", data.len(), String::from_utf8_lossy(&data[0..10.min(data.len())]))
+}
+
+// `seq` is the chunk's sequence number and `offset` is where it goes in the log (stdout and stderr share the log).
+// Old clients don't send them, in which case we just append.
+#[post("/run/<id>/stdout?<seq>&<offset>", data="<data>")]
+#[tracing::instrument(name="POST /run/<id>/stdout", skip(db,auth,data), fields(data=%short_data(&data)))]
+async fn run_stdout(db: &State<Db>, auth: ClientAuth, id: u128, seq: Option<u64>, offset: Option<u64>, data: Vec<u8>) -> WebResult<()> {
+    run_stdio(db, auth, id, seq, offset, data, OutKind::Stdout).await
 }
 
+#[post("/run/<id>/stderr?<seq>&<offset>", data="<data>")]
+#[tracing::instrument(name="POST /run/<id>/stderr", skip(db,auth,data), fields(data=%short_data(&data)))]
+async fn run_stderr(db: &State<Db>, auth: ClientAuth, id: u128, seq: Option<u64>, offset: Option<u64>, data: Vec<u8>) -> WebResult<()> {
+    run_stdio(db, auth, id, seq, offset, data, OutKind::Stderr).await
+}
 
-fn short_data(data: &str) -> String {
-    format!("[{}]{}...", data.len(), data.get(0..10.min(data.len())).unwrap_or(r"¯\_(ツ)_/¯"))
+async fn run_stdio(db: &State<Db>, auth: ClientAuth, id: u128, seq: Option<u64>, offset: Option<u64>, data: Vec<u8>, kind: OutKind) -> WebResult<()> {
+    let run = auth.run(db, id).await?;
+    match offset {
+        Some(offset) => run.add_output(kind, &data, seq, offset).await?,
+        None         => run.add_stdout(kind, &data).await?,
+    }
+    Ok(())
 }
 
-#[post("/run/<id>/stdout", data="<data>")]
-#[tracing::instrument(name="POST /run/<id>/stdout", skip(db,data), fields(data=%short_data(&data)))]
-async fn run_stdout(db: &State<Db>, id: u128, data: String) -> WebResult<()> {
-    run_stdio(db, id, data, OutKind::Stdout).await
+// The streaming version of /run/<id>/stdout and /run/<id>/stderr: the body is a series of OutputFrames. This saves
+// the client from having to make a request for each chunk of output. The client ends the request every so often
+// so it can find out what it can stop holding on to in case it has to retry.
+#[post("/run/<id>/output", data="<data>")]
+#[tracing::instrument(name="POST /run/<id>/output", skip(db,auth,data), ret)]
+async fn run_output(db: &State<Db>, auth: ClientAuth, id: u128, data: rocket::Data<'_>) -> WebResult<Json<OutputResp>> {
+    use rocket::data::ToByteUnit;
+    let run = auth.run(db, id).await?;
+    let mut body = tokio::io::BufReader::new(data.open(1.gibibytes()));
+    loop {
+        let Some(frame) = OutputFrame::read(&mut body).await? else { break };
+        run.add_output(frame.kind, &frame.chunk, Some(frame.seq), frame.offset).await?;
+    }
+    // Frames don't always come in order (stdout and stderr race each other to the client's upload), so what we have
+    // is however much of the log is there without a gap. Chunks that are parked past a gap don't count yet.
+    Ok(Json(OutputResp { received: run.log_len() }))
 }
 
-#[post("/run/<id>/stderr", data="<data>")]
-#[tracing::instrument(name="POST /run/<id>/stderr", skip(db,data), fields(data=%short_data(&data)))]
-async fn run_stderr(db: &State<Db>, id: u128, data: String) -> WebResult<()> {
-    run_stdio(db, id, data, OutKind::Stderr).await
+// A file the job left in $SYNCRON_ARTIFACTS. Sending one again replaces it.
+#[post("/run/<id>/artifact/<name..>", data="<data>")]
+#[tracing::instrument(name="POST /run/<id>/artifact/<name..>", skip(db,auth,data), ret)]
+async fn run_artifact(db: &State<Db>, auth: ClientAuth, id: u128, name: PathBuf, data: rocket::Data<'_>) -> WebResult<()> {
+    use rocket::data::ToByteUnit;
+    let run = auth.run(db, id).await?;
+    let path = run.new_artifact_path(&name).await?;
+    let file = data.open(1.gibibytes()).into_file(&path).await.map_err(|e| wrap(&e, &format!("write {}", path.to_string_lossy())))?;
+    if !file.is_complete() {
+        tokio::fs::remove_file(&path).await.map_err(|e| wrap(&e, &format!("rm {}", path.to_string_lossy())))?;
+        Err(Debug(format!("Artifact {} is too big", name.to_string_lossy()).into()))?
+    }
+    Ok(())
 }
 
-async fn run_stdio(db: &State<Db>, id: u128, data: String, _kind: OutKind) -> WebResult<()> {
-    let run = db::Run::from_client_id(db, id).await?;
-    run.add_stdout(&data).await?;
+// The Markdown the job wrote to $SYNCRON_SUMMARY.
+#[post("/run/<id>/summary", data="<data>")]
+#[tracing::instrument(name="POST /run/<id>/summary", skip(db,auth,data), ret)]
+async fn run_summary(db: &State<Db>, auth: ClientAuth, id: u128, data: rocket::Data<'_>) -> WebResult<()> {
+    use rocket::data::ToByteUnit;
+    let run = auth.run(db, id).await?;
+    let summary = data.open(1.mebibytes()).into_string().await.map_err(|e| wrap(&e, "read summary"))?;
+    if !summary.is_complete() { Err(Debug("Summary is too big (1MiB max)".into()))? }
+    run.set_summary(&summary).await?;
     Ok(())
 }
 
-#[post("/run/<id>/complete", data="<status>")]
-#[tracing::instrument(name="POST /run/<id>/complete", skip(db), ret)]
-async fn run_complete(db: &State<Db>, id: u128, status: Json<db::ExitStatus>) -> WebResult<()> {
-    let run = db::Run::from_client_id(db, id).await?;
-    run.complete(*status).await?;
+// `end` is ms since the epoch and is only passed when replaying a spooled run.
+// `len` is the total number of bytes of output the client sent so we can tell if any got lost.
+#[post("/run/<id>/complete?<end>&<len>", data="<req>")]
+#[tracing::instrument(name="POST /run/<id>/complete", skip(db,auth), ret)]
+async fn run_complete(db: &State<Db>, auth: ClientAuth, id: u128, end: Option<i64>, len: Option<u64>, req: Json<CompleteBody>) -> WebResult<()> {
+    let run = auth.run(db, id).await?;
+    let req = match req.into_inner() {
+        CompleteBody::Req(req)       => req,
+        CompleteBody::Status(status) => CompleteReq { status, usage: None },
+        CompleteBody::Legacy(LegacyStatus::ClientTimeout) => CompleteReq { status: db::ExitStatus::ClientTimeout(9), usage: None }, // They always SIGKILLed
+    };
+    run.complete_at(req.status, end.unwrap_or_else(|| chrono::Local::now().timestamp_millis()), len, req.usage).await?;
     Ok(())
 }
 
+// Lines the job writes to $SYNCRON_CONTROL (see client::ControlSocket), after the client parses them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Control {
+    Progress { done: f64, total: Option<f64> }, // "progress 42/100", "progress 42%" or just "progress 42"
+    Stage(String),                              // "stage Uploading". An empty stage clears it.
+    Metric { name: String, value: f64 },        // "metric rows=1234"
+}
+
+impl std::str::FromStr for Control {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| n.trim().parse::<f64>().ok().filter(|n| n.is_finite() && *n >= 0.0).ok_or_else(|| format!("Bad number in {:?}", s));
+        let (command, rest) = s.trim().split_once(char::is_whitespace).unwrap_or((s.trim(), ""));
+        match command {
+            "progress" => match (rest.split_once('/'), rest.trim().strip_suffix('%')) {
+                (Some((done, total)), _) => Ok(Control::Progress { done: number(done)?, total: Some(number(total)?) }),
+                (None, Some(percent))    => Ok(Control::Progress { done: number(percent)?, total: Some(100.0) }),
+                (None, None)             => Ok(Control::Progress { done: number(rest)?, total: None }),
+            },
+            "stage"    => Ok(Control::Stage(rest.trim().to_string())),
+            "metric"   => match rest.trim().split_once('=') {
+                Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) =>
+                    Ok(Control::Metric { name: name.to_string(),
+                                         value: value.trim().parse::<f64>().ok().filter(|v| v.is_finite()).ok_or_else(|| format!("Bad number in {:?}", s))? }),
+                _ => Err(format!("Metrics look like \"name=123\", not {:?}", rest.trim())),
+            },
+            _          => Err(format!("Unknown control message: {:?}", s)),
+        }
+    }
+}
+
+#[cfg(test)] #[test] fn test_control_from_str() {
+    assert_eq!("progress 42/100".parse(),   Ok(Control::Progress { done: 42.0, total: Some(100.0) }));
+    assert_eq!("progress  12.5%".parse(),   Ok(Control::Progress { done: 12.5, total: Some(100.0) }));
+    assert_eq!("progress 7".parse(),        Ok(Control::Progress { done: 7.0,  total: None }));
+    assert_eq!("stage  Uploading files ".parse(), Ok(Control::Stage("Uploading files".to_string())));
+    assert_eq!("stage".parse(),             Ok(Control::Stage("".to_string())));
+    assert_eq!("metric rows=1234".parse(),  Ok(Control::Metric { name: "rows".to_string(), value: 1234.0 }));
+    assert_eq!("metric delta=-2.5".parse(), Ok(Control::Metric { name: "delta".to_string(), value: -2.5 }));
+    assert!("metric rows".parse::<Control>().is_err());
+    assert!("metric =12".parse::<Control>().is_err());
+    assert!("metric rows=lots".parse::<Control>().is_err());
+    assert!("progress lots".parse::<Control>().is_err());
+    assert!("progress -1/10".parse::<Control>().is_err());
+    assert!("hello".parse::<Control>().is_err());
+}
+
+#[post("/run/<id>/control", data="<msg>")]
+#[tracing::instrument(name="POST /run/<id>/control", skip(db,auth), ret)]
+async fn run_control(db: &State<Db>, auth: ClientAuth, id: u128, msg: Json<Control>) -> WebResult<()> {
+    let run = auth.run(db, id).await?;
+    let mut report = run.progress_report().await?.unwrap_or_default();
+    match msg.into_inner() {
+        Control::Progress { done, total } => { report.done = Some(done); report.total = total },
+        Control::Stage(stage)             => report.stage = Some(stage).filter(|s| !s.is_empty()),
+        Control::Metric { name, value }   => { run.set_metric(&name, value).await?; return Ok(()) },
+    }
+    run.set_progress_report(&report).await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompleteReq {
+    pub status: db::ExitStatus,
+    #[serde(default)]
+    pub usage:  Option<db::Usage>,
+}
+
+// Older clients just send the ExitStatus
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CompleteBody {
+    Req(CompleteReq),
+    Status(db::ExitStatus),
+    Legacy(LegacyStatus),
+}
+
+// ExitStatuses that have changed shape since older clients were built
+#[derive(Debug, Deserialize)]
+enum LegacyStatus {
+    ClientTimeout,
+}
+
 /////////////////////////////////// Web API ///////////////////////////////////
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Progress {
-    pub percent: f32,
-    pub eta_seconds: u32,
+    pub percent: Option<f32>,     // None if all we know is the stage
+    pub eta_seconds: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,    // What the job says it's doing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub done: Option<f64>,        // A count the job reported without a total
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -205,8 +466,12 @@ pub struct JobInfo {
     pub url: String,
     pub runs_url: String,
     pub success_url: String,
+    #[serde(default)]
+    pub metrics_url: String,
     pub settings_url: String,
     pub prune_url: String,
+    #[serde(default)]
+    pub undelete_url: String,
 }
 
 // Doesn't set latest_run as that is not in JobInfo and this can't be async or fail.
@@ -219,8 +484,10 @@ impl From<&db::Job> for JobInfo {
             url: uri!(get_job(&job.user, &job.id)).to_string(),
             runs_url: uri!(get_runs(&job.user, &job.id, _, _, _, _)).to_string(),
             success_url: uri!(get_success(&job.user, &job.id, _, _)).to_string(),
+            metrics_url: uri!(get_metrics(&job.user, &job.id, _, _, _)).to_string(),
             settings_url: uri!(get_job_settings(&job.user, &job.id)).to_string(),
             prune_url: uri!(get_prune(&job.user, &job.id, _)).to_string(),
+            undelete_url: uri!(undelete_job(&job.user, &job.id)).to_string(),
             latest_run: None,
         }
     }
@@ -249,11 +516,19 @@ pub struct RunInfo {
     pub duration_ms: u64,
     pub id:       String,
     pub status:   Option<db::ExitStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome:  Option<db::Outcome>, // None while it's running (and from older servers)
     pub progress: Option<Progress>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub log_len:  Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub log_url:  Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage:    Option<db::Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempt:  Option<db::Attempt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<i64>,
 }
 
 impl From<&db::Run> for RunInfo {
@@ -261,13 +536,17 @@ impl From<&db::Run> for RunInfo {
         RunInfo{
             unique_id: run.run_db_id,
             status:   None,
+            outcome:  None,
             progress: None,
             date:     run.date.timestamp_millis(),
             duration_ms: run.duration_ms(),
             id:       run.run_id.clone(),
             log_len:  Some(run.log_len()),
-            url:      Some(uri!(get_run(&run.job.user, &run.job.id, &run.run_id, _)).to_string()),
-            log_url:  Some(uri!(get_run_log(&run.job.user, &run.job.id, &run.run_id, _, _)).to_string()),
+            url:      Some(uri!(get_run(&run.job.user, &run.job.id, &run.run_id, _, _)).to_string()),
+            log_url:  Some(uri!(get_run_log(&run.job.user, &run.job.id, &run.run_id, _, _, _)).to_string()),
+            usage:    None,
+            attempt:  None,
+            scheduled: None,
         }
     }
 }
@@ -275,55 +554,67 @@ impl From<&db::Run> for RunInfo {
 impl RunInfo {
     pub async fn try_from_run(run: &db::Run) -> Result<RunInfo, Box<dyn Error>>  {
         let mut r = RunInfo::from(run);
-        r.status = run.info().await.map_err(|e| wrap_str(&*e, "info"))?.status;
-        r.progress = run.progress().map_err(|e| wrap_str(&*e, "progress"))?;
+        let info = run.info().await.map_err(|e| wrap_str(&*e, "info"))?;
+        r.status = info.status;
+        r.outcome = info.outcome;
+        r.usage = info.usage;
+        r.attempt = info.attempt;
+        r.scheduled = info.scheduled.map(|s| s.timestamp_millis());
+        r.progress = run.progress().await.map_err(|e| wrap_str(&*e, "progress"))?;
         Ok(r)
     }
     pub async fn from_run(run: &db::Run) -> RunInfo  {
         let mut r = RunInfo::from(run);
-        r.status = run.get_info().await.ok().map(|ri| ri.status).flatten();
-        r.progress = run.progress().ok().flatten();
+        let info = run.get_info().await.ok();
+        r.status = info.as_ref().and_then(|ri| ri.status);
+        r.outcome = info.as_ref().and_then(|ri| ri.outcome);
+        r.usage = info.as_ref().and_then(|ri| ri.usage);
+        r.scheduled = info.as_ref().and_then(|ri| ri.scheduled).map(|s| s.timestamp_millis());
+        r.attempt = info.and_then(|ri| ri.attempt);
+        r.progress = run.progress().await.ok().flatten();
         r
     }
 }
 
 use rocket::response::{stream,stream::EventStream};
 #[get("/events?<topic>")]
-async fn events(broker: &State<event::Broker>, topic: Vec<&str>) ->  WebResult<EventStream![stream::Event]> {
+async fn events(broker: &State<event::Broker>, auth: WebAuth, topic: Vec<&str>) ->  WebResult<EventStream![stream::Event]> {
     use tokio_stream::StreamExt;
     info!("/events: topic={topic:?}");
     let rx = broker.subscribe(&topic).await?;
-    Ok(EventStream::from(tokio_stream::wrappers::UnboundedReceiverStream::new(rx).map(|e| stream::Event::json(&e))))
+    Ok(EventStream::from(tokio_stream::wrappers::UnboundedReceiverStream::new(rx)
+                         .filter(move |e| e.user().is_none_or(|user| auth.can_see(user)))
+                         .map(|e| stream::Event::json(&e))))
 }
 
 #[get("/jobs")]
-#[tracing::instrument(name="GET /jobs", skip(db))]
-async fn jobs(db: &State<Db>) -> WebResult<Json<Vec<JobInfo>>> {
+#[tracing::instrument(name="GET /jobs", skip(db,auth))]
+async fn jobs(db: &State<Db>, auth: WebAuth) -> WebResult<Json<Vec<JobInfo>>> {
     use rocket::futures::stream::{self, StreamExt, TryStreamExt};
     let jobs = db::Job::jobs(&db).await.map_err(|e| wrap(&*e, "jobs"))?;
-    Ok(Json(stream::iter(jobs.iter())
+    Ok(Json(stream::iter(jobs.iter().filter(|job| auth.can_see(&job.user)))
             .then(async move |job: &db::Job| -> Result<JobInfo, Box<dyn Error>> {
                     Ok(JobInfo::try_from_job(&job, None).await?)
             }).try_collect().await?))
 }
 
 #[get("/runs?<after>&<id>")]
-async fn recent_runs(db: &State<Db>, after: Option<u64>, id:Option<Vec<u64>>) -> WebResult<Json<Vec<JobInfo>>> {
+async fn recent_runs(db: &State<Db>, auth: WebAuth, after: Option<u64>, id:Option<Vec<u64>>) -> WebResult<Json<Vec<JobInfo>>> {
     use rocket::futures::stream::{self, StreamExt, TryStreamExt};
     let runs = match (after,id) {
         (Some(after), None) => db::Run::most_recent(&db, after).await?,
         (None, Some(id)) => db::Run::runs_from_ids(&db, &id).await?,
         (_, _) => return Err(Debug(Box::<dyn Error + Send + Sync>::from(format!("Need 'after' xor 'id' parameters")))),
     };
-    Ok(Json(stream::iter(runs.iter())
+    Ok(Json(stream::iter(runs.iter().filter(|run| auth.can_see(&run.job.user)))
             .then(async move |run: &db::Run| -> Result<JobInfo, Box<dyn Error>> {
                 Ok(JobInfo::try_from_job(&run.job, Some(&run)).await?)
             }).try_collect().await?))
 }
 
 #[get("/job/<user>/<job_id>")]
-async fn get_job(db: &State<Db>, user: &str, job_id: &str) -> WebResult<Option<Json<JobInfo>>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+async fn get_job(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str) -> WebResult<Option<Json<JobInfo>>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
     Ok(Some(Json(JobInfo::try_from_job(&job, None).await?)))
 }
 
@@ -337,12 +628,29 @@ pub struct RunInfoFull {
     pub log:      Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub seek:     Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub log_gaps: Vec<db::LogGap>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub log_matches: Vec<db::LogMatch>, // Lines that matched the job's log rules
+    #[serde(default, skip_serializing_if = "db::Metrics::is_empty")]
+    pub metrics:  db::Metrics,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<ArtifactInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary:  Option<String>, // HTML, rendered from the Markdown the job wrote to $SYNCRON_SUMMARY
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    #[serde(flatten)]
+    pub artifact: db::Artifact,
+    pub url:      String,
 }
 
 #[get("/job/<user>/<job_id>/run?<num>&<before>&<after>&<id>")]
-#[tracing::instrument(name="GET /job/<user>/<job_id>/run", skip(db))]
-async fn get_runs(db: &State<Db>, user: &str, job_id: &str, num: Option<u32>, before: Option<u64>, after: Option<u64>, id:Option<Vec<&str>>) -> WebResult<Option<Json<Vec<RunInfo>>>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+#[tracing::instrument(name="GET /job/<user>/<job_id>/run", skip(db,auth))]
+async fn get_runs(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, num: Option<u32>, before: Option<u64>, after: Option<u64>, id:Option<Vec<&str>>) -> WebResult<Option<Json<Vec<RunInfo>>>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
     use rocket::futures::stream::{self, StreamExt, TryStreamExt};
     let jobs = match id {
         Some(id) if id.len() > 0  => job.runs_from_ids(&id).await?,
@@ -355,46 +663,73 @@ async fn get_runs(db: &State<Db>, user: &str, job_id: &str, num: Option<u32>, be
             }).try_collect().await?)))
 }
 
-#[get("/job/<user>/<job_id>/run/<run_id>?<seek>")]
-#[tracing::instrument(name="GET /job/<user>/<job_id>/run/<run_id>?<seek>", skip(db))]
-async fn get_run(db: &State<Db>, user: &str, job_id: &str, run_id: &str, seek: Option<u64>) -> WebResult<Option<Json<RunInfoFull>>> {
+// `stream` limits the log to just stdout or stderr. `log_len` and `seek` are then in terms of just that stream's output.
+#[get("/job/<user>/<job_id>/run/<run_id>?<seek>&<stream>")]
+#[tracing::instrument(name="GET /job/<user>/<job_id>/run/<run_id>?<seek>&<stream>", skip(db,auth))]
+async fn get_run(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, run_id: &str, seek: Option<u64>, stream: Option<OutKind>) -> WebResult<Option<Json<RunInfoFull>>> {
     //Err(Debug(format!("This is a test")))?;
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
     let run = job.run(run_id).await.map_err(|e| wrap(&*e, "run"))?;
     let info = run.info().await.map_err(|e| wrap(&*e, "info"))?;
-    let (log, log_len, log_url) = match run.log_len() {
+    let log_url = uri!(get_run_log(user, job_id, run_id, _, _, _)).to_string() + &stream.map(|s| format!("?stream={}", s)).unwrap_or_default();
+    let log_len = match stream {
+        Some(stream) => run.stream_len(stream).await.map_err(|e| wrap(&*e, "stream_len"))?,
+        None         => run.log_len(),
+    };
+    let (log, log_len, log_url) = match log_len {
         0                     => (None, None, None),
         log_len @ 1...300_000 => { // If it's short enough, give the log back inline
             use tokio::io::AsyncReadExt;
-            let Some(mut log_file) = run.log_file().await.map_err(|e| wrap(&*e, "log"))? else { Err(Debug(format!("log_len() was {} but log() said None!", log_len).into()))? };
-            let (_total, length) = seek_and_limit(&mut log_file, seek, None).await.map_err(|e| wrap(&*e, "log seek"))?;
-            let mut log = String::with_capacity(length as usize);
-            log_file.read_to_string(&mut log).await.map_err(|e| wrap(&e, "log read"))?;
-            (Some(log), Some(log_len), Some(uri!(get_run_log(user, job_id, run_id, _, _)).to_string()))
+            let log = match stream {
+                Some(stream) => String::from_utf8_lossy(&run.read_stream(stream, seek, None).await.map_err(|e| wrap(&*e, "log read"))?.1).into_owned(),
+                None         => {
+                    let Some(mut log_file) = run.log_file().await.map_err(|e| wrap(&*e, "log"))? else { Err(Debug(format!("log_len() was {} but log() said None!", log_len).into()))? };
+                    let (_total, length) = seek_and_limit(&mut log_file, seek, None).await.map_err(|e| wrap(&*e, "log seek"))?;
+                    let mut log = Vec::with_capacity(length as usize);
+                    log_file.read_to_end(&mut log).await.map_err(|e| wrap(&e, "log read"))?;
+                    String::from_utf8_lossy(&log).into_owned()
+                },
+            };
+            (Some(log), Some(log_len), Some(log_url))
         },
-        log_len               => (None, Some(log_len), Some(uri!(get_run_log(user, job_id, run_id, _, _)).to_string())),
+        log_len               => (None, Some(log_len), Some(log_url)),
     };
+    let summary_html = run.summary().await.map_err(|e| wrap(&*e, "summary"))?.map(|md| render_summary(&md));
     Ok(Some(Json(RunInfoFull{
         run_info: RunInfo {
             unique_id: run.run_db_id,
             status:   info.status,
-            progress: run.progress().map_err(|e| wrap(&*e, "progress"))?,
+            outcome:  info.outcome,
+            progress: run.progress().await.map_err(|e| wrap(&*e, "progress"))?,
             date:     run.date.timestamp_millis(),
             duration_ms: run.duration_ms(),
             id:       run.run_id.clone(),
             url:      None,
             log_len:  log_len,
             log_url:  log_url,
+            usage:    info.usage,
+            attempt:  info.attempt,
+            scheduled: info.scheduled.map(|s| s.timestamp_millis()),
         },
         cmd:      info.cmd,
         env:      info.env,
         log:      log,
         seek:     seek,
+        log_gaps: info.log_gaps,
+        log_matches: info.log_matches,
+        metrics:  info.metrics,
+        artifacts: run.artifacts().map_err(|e| wrap(&*e, "artifacts"))?.into_iter()
+                      .map(|artifact| ArtifactInfo { url: uri!(get_run_artifact(user, job_id, run_id, PathBuf::from(&artifact.name))).to_string(), artifact })
+                      .collect(),
+        summary:  summary_html,
     })))
 }
 
+trait LogBody: tokio::io::AsyncRead + tokio::io::AsyncSeek + Send {}
+impl<T: tokio::io::AsyncRead + tokio::io::AsyncSeek + Send> LogBody for T {}
+
 struct LogStreamer {
-    log: tokio::fs::File,
+    log: std::pin::Pin<Box<dyn LogBody>>,
     len: u64,
     total: u64,
 }
@@ -409,16 +744,36 @@ impl<'r> Responder<'r, 'static> for LogStreamer {
     }
 }
 
-#[get("/job/<user>/<job_id>/run/<run_id>/log?<seek>&<limit>")]
-#[tracing::instrument(name="GET /job/<user>/<job_id>/run/<run_id>/log?<seek>&<limit>", skip(db))]
-async fn get_run_log(db: &State<Db>, user: &str, job_id: &str, run_id: &str, seek: Option<u64>, limit: Option<i64>) -> WebResult<Option<LogStreamer>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+#[get("/job/<user>/<job_id>/run/<run_id>/log?<seek>&<limit>&<stream>")]
+#[tracing::instrument(name="GET /job/<user>/<job_id>/run/<run_id>/log?<seek>&<limit>&<stream>", skip(db,auth))]
+async fn get_run_log(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, run_id: &str, seek: Option<u64>, limit: Option<i64>, stream: Option<OutKind>) -> WebResult<Option<LogStreamer>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
     let run = job.run(run_id).await.map_err(|e| wrap(&*e, "run"))?;
+    if let Some(stream) = stream {
+        if run.log_file().await.map_err(|e| wrap(&*e, "log"))?.is_none() { return Ok(None) }
+        let (total, log) = run.read_stream(stream, seek, limit).await.map_err(|e| wrap(&*e, "read_stream"))?;
+        return Ok(Some(LogStreamer { len: log.len() as u64, total, log: Box::pin(std::io::Cursor::new(log)) }));
+    }
     let Some(mut log) = run.log_file().await.map_err(|e| wrap(&*e, "log"))? else {
         return Ok(None);
     };
     let (total, len) = seek_and_limit(&mut log, seek, limit).await.map_err(|e| wrap(&*e, "seek_and_limit"))?;
-    Ok(Some(LogStreamer { log, total, len }))
+    Ok(Some(LogStreamer { log: Box::pin(log), total, len }))
+}
+
+#[get("/job/<user>/<job_id>/run/<run_id>/summary")]
+async fn get_run_summary(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, run_id: &str) -> WebResult<Option<(ContentType, String)>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
+    let run = job.run(run_id).await.map_err(|e| wrap(&*e, "run"))?;
+    Ok(run.summary().await.map_err(|e| wrap(&*e, "summary"))?.map(|md| (ContentType::HTML, render_summary(&md))))
+}
+
+#[get("/job/<user>/<job_id>/run/<run_id>/artifact/<name..>")]
+async fn get_run_artifact(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, run_id: &str, name: PathBuf) -> WebResult<Option<rocket::fs::NamedFile>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
+    let run = job.run(run_id).await.map_err(|e| wrap(&*e, "run"))?;
+    let Some(path) = run.artifact_path(&name) else { return Ok(None) };
+    Ok(Some(rocket::fs::NamedFile::open(&path).await.map_err(|e| wrap(&e, &format!("open {}", path.to_string_lossy())))?))
 }
 
 pub (crate) async fn seek_and_limit(f: &mut tokio::fs::File, seek: Option<u64>, limit: Option<i64>) -> Result<(u64, u64), Box<dyn Error>> {
@@ -457,21 +812,40 @@ pub fn apply_limit(len: u64, seek: Option<u64>, limit: Option<i64>) -> (u64, u64
 
 
 #[get("/job/<user>/<job_id>/success?<before>&<after>")]
-async fn get_success(db: &State<Db>, user: &str, job_id: &str, before: Option<u64>, after: Option<u64>) -> WebResult<Option<Json<Vec<(i64,Option<bool>)>>>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+async fn get_success(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, before: Option<u64>, after: Option<u64>) -> WebResult<Option<Json<Vec<(i64,Option<db::Outcome>)>>>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
     Ok(Some(Json(job.successes(before, after).await?)))
 }
 
+// What the job's runs reported with `syncron metric`, as a time series: [[start, {name: value, ...}], ...]
+#[get("/job/<user>/<job_id>/metrics?<name>&<before>&<after>")]
+async fn get_metrics(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, name: Option<&str>, before: Option<u64>, after: Option<u64>) -> WebResult<Option<Json<Vec<(i64, db::Metrics)>>>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
+    Ok(Some(Json(job.metrics(name, before, after).await?)))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReevaluateResult {
+    changed: usize,
+}
+
+// Judges the job's old runs again with its current success policy.
+#[post("/job/<user>/<job_id>/success")]
+async fn post_success(db: &State<Db>, auth: Operator, user: &str, job_id: &str) -> WebResult<Option<Json<ReevaluateResult>>> {
+    let Some(job) = auth.0.job(db, user, job_id).await? else { return Ok(None) };
+    Ok(Some(Json(ReevaluateResult { changed: job.reevaluate_successes().await? })))
+}
+
 
 #[get("/job/<user>/<job_id>/settings")]
-async fn get_job_settings(db: &State<Db>, user: &str, job_id: &str) -> WebResult<Option<Json<db::JobSettings>>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+async fn get_job_settings(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str) -> WebResult<Option<Json<db::JobSettings>>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
     Ok(Some(Json(job.settings)))
 }
 
 #[put("/job/<user>/<job_id>/settings", data="<settings>")]
-async fn put_job_settings(db: &State<Db>, user: &str, job_id: &str, settings: Json<db::JobSettings>) -> WebResult<Option<()>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+async fn put_job_settings(db: &State<Db>, auth: Operator, user: &str, job_id: &str, settings: Json<db::JobSettings>) -> WebResult<Option<()>> {
+    let Some(job) = auth.0.job(db, user, job_id).await? else { return Ok(None) };
     job.update_settings(&settings).await?;
     Ok(Some(()))
 }
@@ -484,34 +858,150 @@ struct PruneResult {
 }
 
 #[get("/job/<user>/<job_id>/prune?<settings>")]
-async fn get_prune(db: &State<Db>, user: &str, job_id: &str, settings: Option<Json<db::RetentionSettings>>) -> WebResult<Option<Json<PruneResult>>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+async fn get_prune(db: &State<Db>, auth: WebAuth, user: &str, job_id: &str, settings: Option<Json<db::RetentionSettings>>) -> WebResult<Option<Json<PruneResult>>> {
+    let Some(job) = auth.job(db, user, job_id).await? else { return Ok(None) };
     let (stats, pruned) = job.prune_dry_run(settings.map(|s| s.into_inner())).await?;
     Ok(Some(Json(PruneResult { pruned, stats })))
 }
 
 #[post("/job/<user>/<job_id>/prune")]
-async fn post_prune(db: &State<Db>, user: &str, job_id: &str) -> WebResult<Option<Json<PruneResult>>> {
-    let Some(job) = db::Job::new(&db, user, job_id).await.map_err(|e| wrap(&*e, "db::Job"))? else { return Ok(None) };
+async fn post_prune(db: &State<Db>, auth: Operator, user: &str, job_id: &str) -> WebResult<Option<Json<PruneResult>>> {
+    let Some(job) = auth.0.job(db, user, job_id).await? else { return Ok(None) };
     let (stats, pruned) = job.prune_with_stats().await?;
     Ok(Some(Json(PruneResult { pruned, stats })))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Settings {
-    retention: db::RetentionSettings,
+struct DeleteResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purge_at: Option<i64>, // When it'll be gone for good. POSTing to its undelete_url before then brings it back.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    purging:  bool,        // It's being purged right now. Progress goes out on the job's delete event topic.
+}
+
+// Deleted jobs are kept around (hidden) for a day in case it was a mistake, unless `purge` is set.
+#[delete("/job/<user>/<job_id>?<purge>")]
+async fn delete_job(db: &State<Db>, auth: Operator, user: &str, job_id: &str, purge: Option<bool>) -> WebResult<Option<Json<DeleteResult>>> {
+    let purge = purge.unwrap_or(false);
+    let found = auth.0.job(db, user, job_id).await?;
+    let job = match found {
+        Some(job)         => job,
+        None if purge     => { // Already deleted jobs can still be purged
+            let deleted = auth.0.deleted_job(db, user, job_id).await?;
+            let Some(job) = deleted else { return Ok(None) };
+            purge_in_background(job);
+            return Ok(Some(Json(DeleteResult { purge_at: None, purging: true })));
+        },
+        None              => return Ok(None),
+    };
+    let purge_at = job.delete().await?;
+    Ok(Some(Json(match purge {
+        true  => { purge_in_background(job); DeleteResult { purge_at: None, purging: true } },
+        false => DeleteResult { purge_at: Some(purge_at), purging: false },
+    })))
+}
+
+// A job with lots of runs can take a long time to purge--too long to make the request wait for it.
+fn purge_in_background(job: db::Job) {
+    tokio::spawn(async move {
+        match job.purge().await {
+            Ok(purged) => info!("{}/{}: Purged {} runs ({})", job.user, job.id, purged.runs, crate::human_bytes(purged.size)),
+            Err(e)     => warn!("{}/{}: error purging: {}", job.user, job.id, e),
+        }
+    });
+}
+
+#[post("/job/<user>/<job_id>/undelete")]
+async fn undelete_job(db: &State<Db>, auth: Operator, user: &str, job_id: &str) -> WebResult<Option<Json<JobInfo>>> {
+    let Some(job) = auth.0.deleted_job(db, user, job_id).await? else { return Ok(None) };
+    job.undelete().await?;
+    Ok(Some(Json(JobInfo::try_from_job(&job, None).await?)))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameReq {
+    pub name: Option<String>,
+    pub id:   Option<String>, // Its runs move to the new id. The old one keeps working as an alias.
+}
+
+#[post("/job/<user>/<job_id>/rename", data="<req>")]
+async fn rename_job(db: &State<Db>, auth: Operator, user: &str, job_id: &str, req: Json<RenameReq>) -> WebResult<Option<Json<JobInfo>>> {
+    let Some(job) = auth.0.job(db, user, job_id).await? else { return Ok(None) };
+    let job = job.rename(req.name.as_deref(), req.id.as_deref()).await?;
+    Ok(Some(Json(JobInfo::try_from_job(&job, None).await?)))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MergeReq {
+    pub from: String, // The id of the job whose runs move into this one. It goes away and its id becomes an alias of this job.
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MergeResult {
+    merged: u64, // Runs that moved
+    job:    JobInfo,
+}
+
+#[post("/job/<user>/<job_id>/merge", data="<req>")]
+async fn merge_job(db: &State<Db>, auth: Operator, user: &str, job_id: &str, req: Json<MergeReq>) -> WebResult<Option<Json<MergeResult>>> {
+    let Some(job)  = auth.0.job(db, user, job_id).await?   else { return Ok(None) };
+    let Some(from) = auth.0.job(db, user, &req.from).await? else { return Ok(None) };
+    let merged = job.merge(&from).await?;
+    Ok(Some(Json(MergeResult { merged, job: JobInfo::try_from_job(&job, None).await? })))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Settings {
+    pub retention: db::RetentionSettings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<RedactionRules>, // Always there from GET. Left alone by a PUT that doesn't have it.
 }
 
 #[get("/settings")]
-async fn get_settings(db: &State<Db>) -> WebResult<Json<Settings>> {
+async fn get_settings(db: &State<Db>, _auth: AnyAuth) -> WebResult<Json<Settings>> {
     let settings = db::Settings::load(db).await?;
-    Ok(Json(Settings { retention: settings.retention }))
+    Ok(Json(Settings { retention: settings.retention, redaction: Some(settings.redaction) }))
 }
 
 #[put("/settings", data="<new_settings>")]
-async fn put_settings(db: &State<Db>, new_settings: Json<Settings>) -> WebResult<()> {
+async fn put_settings(db: &State<Db>, _auth: Admin, new_settings: Json<Settings>) -> WebResult<()> {
     let mut settings = db::Settings::load(db).await?;
     settings.set_retention(new_settings.retention).await?;
+    if let Some(ref redaction) = new_settings.redaction {
+        settings.set_redaction(redaction.clone()).await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginReq {
+    pub name:     String,
+    pub password: String,
+}
+
+// Who's logged in. `null` when there aren't any accounts, which means everyone can do everything.
+#[get("/account")]
+async fn get_account(auth: WebAuth) -> Json<Option<auth::Account>> {
+    Json(auth.account().cloned())
+}
+
+#[post("/login", data="<req>")]
+#[tracing::instrument(name="POST /login", skip_all, fields(name=req.name))]
+async fn login(db: &State<Db>, cookies: &CookieJar<'_>, req: Json<LoginReq>) -> WebResult<Result<Json<auth::Account>, Status>> {
+    let Some((account, secret)) = auth::Account::login(db, &req.name, &req.password).await? else {
+        warn!("Failed login for {:?}", req.name);
+        return Ok(Err(Status::Unauthorized));
+    };
+    cookies.add(auth::session_cookie(secret));
+    Ok(Ok(Json(account)))
+}
+
+#[post("/logout")]
+async fn logout(db: &State<Db>, cookies: &CookieJar<'_>) -> WebResult<()> {
+    if let Some(secret) = auth::session_secret(cookies) {
+        auth::Account::logout(db, &secret).await?;
+    }
+    cookies.remove(auth::SESSION_COOKIE);
     Ok(())
 }
 
@@ -530,11 +1020,22 @@ pub async fn serve(port: u16, db: &Db, enable_shutdown: bool) -> Result<(), Box<
         .select(figment::Profile::from_env_or("APP_PROFILE", "default"));
     let mut routes = routes![index, files, docs_index, docs,
                              // client endpoints
-                             run_create, run_heartbeat, run_stdout, run_stderr, run_complete,
+                             run_create, run_heartbeat, run_stdout, run_stderr, run_output, run_control, run_artifact, run_summary, run_complete,
                              // web app endpoints
-                             events, jobs, recent_runs, get_job, get_runs, get_run, get_run_log, get_success,
-                             get_job_settings, put_job_settings, get_prune, post_prune, get_settings, put_settings];
+                             events, jobs, recent_runs, get_job, get_runs, get_run, get_run_log, get_run_summary, get_run_artifact, get_success, post_success, get_metrics,
+                             get_job_settings, put_job_settings, get_prune, post_prune, get_settings, put_settings,
+                             delete_job, undelete_job, rename_job, merge_job, get_account, login, logout];
     if enable_shutdown { routes.append(&mut routes![shutdown]) }
+    let purger = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(10*60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = db::Job::purge_deleted(&purger).await {
+                warn!("Couldn't purge deleted jobs: {}", e);
+            }
+        }
+    });
     let _rocket = rocket::custom(figment)
         .mount("/", routes)
         .manage(db.clone())
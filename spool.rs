@@ -0,0 +1,383 @@
+// Copyright © 2026 David Caldwell <david@porkrind.org>
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+use crate::client::Api;
+use crate::redact::{RedactionRules, Redactor};
+use crate::{db, serve};
+
+// When the server can't be reached we still want a record of the run, so the client captures everything into a
+// "spool" directory on the local disk and replays it to the server later (`syncron flush`, or after the next
+// `syncron exec` that manages to talk to the server). Each spooled run gets its own directory:
+//
+//   lock        - flock()ed by the client while the job is running and by whoever is replaying it.
+//   run.json    - The serve::CreateRunReq we would have sent (including the real start time). The environment gets
+//                 the default redaction rules since we couldn't get the server's.
+//   output      - serve::OutputFrames, exactly as they'd be sent to the server.
+//   artifacts/  - What the job left in $SYNCRON_ARTIFACTS. Uploaded just before the run is completed.
+//   summary.md  - What the job wrote to $SYNCRON_SUMMARY. Also uploaded just before the run is completed.
+//   status.json - Exit status and end time. Only written once the job finishes. If it's missing and nobody holds
+//                 the lock then the client died (or the machine rebooted) while the job was running.
+//   attempt_of  - Only for retries: the name of the first attempt's directory. The client keeps the first attempt
+//                 locked until the last one is done so they all get replayed together, and the retry gets tied to
+//                 the first attempt on the server.
+//   replay.json - How far a replay got, so a replay that gets interrupted can pick back up where it left off
+//                 instead of creating a duplicate run.
+//
+// Runs the server refuses to take (it answers with a 4xx) get moved into the "rejected" directory so they don't hold
+// up the rest. They're left there for someone to look at. Auth failures, timeouts and rate limits don't count--see
+// Api::is_rejection().
+
+const REJECTED: &str = "rejected";
+
+// The server said no to a spooled run. See Api::is_rejection().
+#[derive(Debug)]
+struct Rejected(anyhow::Error);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for Rejected {}
+
+fn api_error(e: anyhow::Error) -> Box<dyn Error> {
+    if Api::is_rejection(&e) { Box::new(Rejected(e)) } else { e.into() }
+}
+
+#[derive(Debug, Clone)]
+pub struct Spool {
+    dir: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct SpoolRun {
+    dir: PathBuf,
+    _lock: std::fs::File, // Dropping this releases the flock()
+    output: Mutex<SpoolOutput>,
+}
+
+#[derive(Debug)]
+struct SpoolOutput {
+    file:   tokio::fs::File,
+    seq:    u64,
+    offset: u64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct SpoolStatus {
+    status: db::ExitStatus,
+    end:    i64,
+    #[serde(default)]
+    usage:  Option<db::Usage>,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct Replay {
+    id:       Option<String>, // Server's client id for the run once it's been created
+    #[serde(default)]
+    run_id:   Option<String>, // ...and its run id, which retries of it need
+    sent:     u64,            // Offset into `output` of the first frame the server doesn't have yet
+    received: u64,            // How much of the log the server says it has
+    complete: bool,
+}
+
+pub fn default_dir() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".syncron").join("spool"),
+        None       => std::env::temp_dir().join(format!("syncron-spool-{}", nix::unistd::getuid())),
+    }
+}
+
+fn lock(dir: &Path, create: bool) -> Result<Option<std::fs::File>, Box<dyn Error>> {
+    let path = dir.join("lock");
+    let file = std::fs::File::options().create(create).write(true).open(&path).map_err(|e| crate::wrap(e, &format!("open {}", path.to_string_lossy())))?;
+    match nix::fcntl::flock(file.as_raw_fd(), nix::fcntl::FlockArg::LockExclusiveNonblock) {
+        Ok(())                            => Ok(Some(file)),
+        Err(nix::errno::Errno::EWOULDBLOCK) => Ok(None),
+        Err(e)                            => Err(crate::wrap(e, &format!("flock {}", path.to_string_lossy()))),
+    }
+}
+
+fn write_json<T: serde::Serialize>(path: &Path, value: &T) -> Result<(), Box<dyn Error>> {
+    // Write then rename so a crash never leaves a half written file behind.
+    let temp = path.with_extension("new");
+    std::fs::write(&temp, serde_json::to_string(value)?).map_err(|e| crate::wrap(e, &format!("write {}", temp.to_string_lossy())))?;
+    std::fs::rename(&temp, path).map_err(|e| crate::wrap(e, &format!("rename {}", path.to_string_lossy())))?;
+    Ok(())
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, Box<dyn Error>> {
+    match std::fs::read(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e)                                             => Err(crate::wrap(e, &format!("read {}", path.to_string_lossy()))),
+        Ok(bytes)                                          => Ok(Some(serde_json::from_slice(&bytes).map_err(|e| crate::wrap(e, &format!("parse {}", path.to_string_lossy())))?)),
+    }
+}
+
+impl Spool {
+    pub fn new(dir: PathBuf) -> Spool {
+        Spool { dir }
+    }
+
+    // `attempt_of` is the first attempt, when this is a retry.
+    pub fn create_run(&self, req: &serve::CreateRunReq, attempt_of: Option<&SpoolRun>) -> Result<SpoolRun, Box<dyn Error>> {
+        let start = req.start.unwrap_or_else(|| chrono::Local::now().timestamp_millis());
+        let mut random = [0; 4];
+        getrandom::getrandom(&mut random)?;
+        // Name them so that sorting by name replays them in the order they ran.
+        let dir = self.dir.join(format!("{:013}-{:08x}", start, u32::from_ne_bytes(random)));
+        std::fs::DirBuilder::new().recursive(true).create(&dir).map_err(|e| crate::wrap(e, &format!("mkdir -p {}", dir.to_string_lossy())))?;
+        let lock = lock(&dir, true)?.ok_or_else(|| format!("{} is already locked", dir.to_string_lossy()))?;
+        let output = std::fs::File::options().create(true).append(true).open(dir.join("output"))?;
+        std::fs::create_dir(dir.join("artifacts")).map_err(|e| crate::wrap(e, &format!("mkdir {}", dir.join("artifacts").to_string_lossy())))?;
+        if let Some(first) = attempt_of {
+            std::fs::write(dir.join("attempt_of"), first.dir.file_name().expect("spool dirs have names").as_bytes())?;
+        }
+        let redactor = Redactor::new(&RedactionRules::default(), &req.env)?;
+        write_json(&dir.join("run.json"), &serve::CreateRunReq { env: redactor.env(&req.env), ..req.clone() })?;
+        debug!("Spooling run to {}", dir.to_string_lossy());
+        Ok(SpoolRun { dir, _lock: lock, output: Mutex::new(SpoolOutput { file: tokio::fs::File::from_std(output), seq: 0, offset: 0 }) })
+    }
+
+    // Returns the number of runs that were replayed
+    pub async fn flush(&self, api: &Api) -> Result<usize, Box<dyn Error>> {
+        let mut dirs = match std::fs::read_dir(&self.dir) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0), // Nothing has ever been spooled
+            Err(e) => Err(crate::wrap(e, &format!("read_dir {}", self.dir.to_string_lossy())))?,
+            Ok(entries) => entries.filter_map(|e| e.ok()).filter(|e| e.file_name() != REJECTED).map(|e| e.path()).filter(|p| p.is_dir()).collect::<Vec<_>>(),
+        };
+        dirs.sort();
+        let mut flushed = 0;
+        let mut run_ids = HashMap::new();
+        for dir in dirs.iter() {
+            match replay(api, dir, &mut run_ids).await {
+                Ok(true)  => {
+                    info!("Replayed spooled run {}", dir.to_string_lossy());
+                    flushed += 1;
+                },
+                Ok(false) => {},
+                Err(e) if e.is::<Rejected>() => {
+                    let rejected = self.dir.join(REJECTED);
+                    warn!("The server rejected spooled run {}: {}. Moving it to {}", dir.to_string_lossy(), e, rejected.to_string_lossy());
+                    std::fs::DirBuilder::new().recursive(true).create(&rejected).map_err(|e| crate::wrap(e, &format!("mkdir -p {}", rejected.to_string_lossy())))?;
+                    let to = rejected.join(dir.file_name().expect("spool dirs have names"));
+                    std::fs::rename(dir, &to).map_err(|e| crate::wrap(e, &format!("mv {} {}", dir.to_string_lossy(), to.to_string_lossy())))?;
+                },
+                // Stop at any other error--it most likely means the server went away again.
+                Err(e) => Err(crate::wrap_str(&*e, &format!("replay {}", dir.to_string_lossy())))?,
+            }
+        }
+        Ok(flushed)
+    }
+}
+
+// Returns false if the run couldn't be replayed yet (it's still running, or someone else is replaying it, or it's a retry
+// and the first attempt isn't on the server yet). `run_ids` maps the spool dirs replayed so far to the server's run ids.
+async fn replay(api: &Api, dir: &Path, run_ids: &mut HashMap<OsString, String>) -> Result<bool, Box<dyn Error>> {
+    let Some(_lock) = lock(dir, false)? else { return Ok(false) };
+    let Some(req) = read_json::<serve::CreateRunReq>(&dir.join("run.json"))? else {
+        warn!("Ignoring spool dir {} with no run.json", dir.to_string_lossy());
+        return Ok(false);
+    };
+    let output_path = dir.join("output");
+    let status = match read_json::<SpoolStatus>(&dir.join("status.json"))? {
+        Some(status) => status,
+        None => {
+            // The client went away without finishing. The closest thing we have is a heartbeat timeout,
+            // and the last time it wrote any output is the best guess we have for when that happened.
+            let last_write = std::fs::metadata(&output_path)?.modified()?;
+            SpoolStatus { status: db::ExitStatus::ServerTimeout,
+                          end: chrono::DateTime::<chrono::Local>::from(last_write).timestamp_millis(),
+                          usage: None }
+        },
+    };
+
+    let replay_path = dir.join("replay.json");
+    let mut replay = read_json::<Replay>(&replay_path)?.unwrap_or_default();
+    if !replay.complete {
+        let id = match replay.id {
+            Some(ref id) => id.clone(),
+            None => {
+                let attempt_of = match std::fs::read(dir.join("attempt_of")) {
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => Err(crate::wrap(e, &format!("read {}", dir.join("attempt_of").to_string_lossy())))?,
+                    Ok(first) => match run_ids.get(OsStr::from_bytes(&first)) {
+                        Some(run_id) => Some(run_id.clone()),
+                        None if dir.with_file_name(OsStr::from_bytes(&first)).exists() => return Ok(false),
+                        None => {
+                            warn!("{} is a retry of a spooled run that's gone. Replaying it on its own", dir.to_string_lossy());
+                            None
+                        },
+                    },
+                };
+                let req = serve::CreateRunReq { attempt_of, ..req };
+                let resp: serve::CreateRunResp = serde_json::from_str(&api.post("/run/create", serde_json::to_string(&req)?.as_bytes()).await.map_err(api_error)?)?;
+                replay.id = Some(resp.id.clone());
+                replay.run_id = Some(resp.run_id);
+                write_json(&replay_path, &replay)?;
+                resp.id
+            },
+        };
+        if let (Some(run_id), Some(name)) = (&replay.run_id, dir.file_name()) {
+            run_ids.insert(name.to_owned(), run_id.clone());
+        }
+
+        let mut output = tokio::io::BufReader::new(tokio::fs::File::open(&output_path).await?);
+        output.seek(std::io::SeekFrom::Start(replay.sent)).await?;
+        let mut last_heartbeat = std::time::Instant::now();
+        loop {
+            if last_heartbeat.elapsed() > std::time::Duration::from_secs(10) { // Don't let the server time us out if this takes a while
+                api.post(&format!("/run/{}/heartbeat", id), &[]).await.map_err(api_error)?;
+                last_heartbeat = std::time::Instant::now();
+            }
+            // Send it up in batches so an interruption doesn't cost us too much.
+            let mut batch = vec![];
+            while batch.len() < 1024*1024 {
+                let Some(frame) = serve::OutputFrame::read(&mut output).await? else { break };
+                batch.extend_from_slice(&frame.to_bytes());
+            }
+            if batch.is_empty() { break }
+            // If we die right after this, the next replay will send the batch again. The server will know it already has it.
+            let resp: serve::OutputResp = serde_json::from_str(&api.post(&format!("/run/{}/output", id), &batch).await.map_err(api_error)?)?;
+            replay.sent = output.stream_position().await?;
+            replay.received = replay.received.max(resp.received);
+            write_json(&replay_path, &replay)?;
+        }
+
+        crate::client::upload_artifacts(api, &id, &dir.join("artifacts")).await;
+        crate::client::upload_summary(api, &id, &dir.join("summary.md"), None).await; // The server masks it
+        api.post(&format!("/run/{}/complete?end={}&len={}", id, status.end, replay.received),
+                 serde_json::to_string(&serve::CompleteReq { status: status.status, usage: status.usage })?.as_bytes()).await.map_err(api_error)?;
+        replay.complete = true;
+        write_json(&replay_path, &replay)?;
+    }
+
+    std::fs::remove_dir_all(dir).map_err(|e| crate::wrap(e, &format!("rm -rf {}", dir.to_string_lossy())))?;
+    Ok(true)
+}
+
+impl SpoolRun {
+    pub async fn write(&self, kind: serve::OutKind, chunk: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut output = self.output.lock().await;
+        let frame = serve::OutputFrame { kind, seq: output.seq, offset: output.offset, chunk: chunk.to_vec() };
+        output.file.write_all(&frame.to_bytes()).await?; // One write per frame so stdout and stderr don't get mixed up
+        output.seq += 1;
+        output.offset = frame.end();
+        Ok(())
+    }
+
+    pub fn artifacts_path(&self) -> PathBuf { self.dir.join("artifacts") }
+    pub fn summary_path(&self)   -> PathBuf { self.dir.join("summary.md") }
+
+    pub fn complete(&self, status: db::ExitStatus, usage: Option<db::Usage>) -> Result<(), Box<dyn Error>> {
+        write_json(&self.dir.join("status.json"), &SpoolStatus { status, end: chrono::Local::now().timestamp_millis(), usage })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames() {
+        let dir = tempfile::Builder::new().prefix("syncron-spool-test").tempdir().unwrap();
+        let spool = Spool::new(dir.path().to_path_buf());
+        let req = serve::CreateRunReq { user: "test-user".into(), name: "Spooled".into(), id: None, cmd: "true".into(), env: vec![], start: Some(1234), attempt_of: None, scheduled: None };
+        let run = spool.create_run(&req, None).unwrap();
+        run.write(serve::OutKind::Stdout, b"some output\n").await.unwrap();
+        run.write(serve::OutKind::Stderr, b"an error\n").await.unwrap();
+        run.write(serve::OutKind::Stdout, b"").await.unwrap();
+        run.write(serve::OutKind::Stdout, b"more\0output").await.unwrap();
+
+        assert!(lock(&run.dir, false).unwrap().is_none(), "run should hold the lock while it's alive");
+
+        let mut output = tokio::io::BufReader::new(tokio::fs::File::open(run.dir.join("output")).await.unwrap());
+        let frame = |kind, seq, offset, chunk: &[u8]| Some(serve::OutputFrame { kind, seq, offset, chunk: chunk.to_vec() });
+        assert_eq!(serve::OutputFrame::read(&mut output).await.unwrap(), frame(serve::OutKind::Stdout, 0, 0,  b"some output\n"));
+        assert_eq!(serve::OutputFrame::read(&mut output).await.unwrap(), frame(serve::OutKind::Stderr, 1, 12, b"an error\n"));
+        assert_eq!(serve::OutputFrame::read(&mut output).await.unwrap(), frame(serve::OutKind::Stdout, 2, 21, b""));
+        assert_eq!(serve::OutputFrame::read(&mut output).await.unwrap(), frame(serve::OutKind::Stdout, 3, 21, b"more\0output"));
+        assert_eq!(serve::OutputFrame::read(&mut output).await.unwrap(), None);
+
+        let dir = run.dir.clone();
+        drop(run);
+        assert!(lock(&dir, false).unwrap().is_some(), "lock should be released when the run is dropped");
+    }
+
+    // A server that gives the same reply to everything
+    async fn fake_server(reply: &'static [u8]) -> Api {
+        use tokio::io::AsyncReadExt;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = Api::new(format!("http://{}/", listener.local_addr().unwrap()).parse().unwrap()).unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                _ = conn.read(&mut request).await;
+                _ = conn.write_all(reply).await;
+            }
+        });
+        api
+    }
+
+    #[tokio::test]
+    async fn test_rejected() {
+        let api = fake_server(b"HTTP/1.1 400 Bad Request\r\ncontent-length: 6\r\nconnection: close\r\n\r\nBad id").await;
+        let dir = tempfile::Builder::new().prefix("syncron-spool-test").tempdir().unwrap();
+        let spool = Spool::new(dir.path().to_path_buf());
+        for start in [1234, 5678] {
+            let req = serve::CreateRunReq { user: "test-user".into(), name: "Spooled".into(), id: None, cmd: "true".into(), env: vec![], start: Some(start), attempt_of: None, scheduled: None };
+            spool.create_run(&req, None).unwrap().complete(db::ExitStatus::Exited(0), None).unwrap();
+        }
+        assert_eq!(spool.flush(&api).await.expect("rejections don't stop the flush"), 0);
+        let left = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect::<Vec<_>>();
+        assert_eq!(left, vec![REJECTED]);
+        assert_eq!(std::fs::read_dir(dir.path().join(REJECTED)).unwrap().count(), 2);
+
+        // Not being able to reach the server at all still stops it, and leaves the runs alone
+        let unreachable = Api::new("http://127.0.0.1:1/".parse().unwrap()).unwrap();
+        spool.create_run(&serve::CreateRunReq { user: "test-user".into(), name: "Spooled".into(), id: None, cmd: "true".into(), env: vec![], start: Some(9999), attempt_of: None, scheduled: None }, None)
+             .unwrap().complete(db::ExitStatus::Exited(0), None).unwrap();
+        assert!(spool.flush(&unreachable).await.is_err());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized() {
+        // A bad token is our problem, not the run's. It should still be there once the token is fixed.
+        let api = fake_server(b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 9\r\nconnection: close\r\n\r\nBad token").await;
+        let dir = tempfile::Builder::new().prefix("syncron-spool-test").tempdir().unwrap();
+        let spool = Spool::new(dir.path().to_path_buf());
+        let req = serve::CreateRunReq { user: "test-user".into(), name: "Spooled".into(), id: None, cmd: "true".into(), env: vec![], start: Some(1234), attempt_of: None, scheduled: None };
+        let run = spool.create_run(&req, None).unwrap();
+        let run_dir = run.dir.clone();
+        run.complete(db::ExitStatus::Exited(0), None).unwrap();
+        drop(run);
+        assert!(spool.flush(&api).await.is_err(), "a 401 stops the flush");
+        let left = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+        assert_eq!(left, vec![run_dir]);
+    }
+
+    #[test]
+    fn test_env_redacted() {
+        use crate::maybe_utf8::MaybeUTF8;
+        let dir = tempfile::Builder::new().prefix("syncron-spool-test").tempdir().unwrap();
+        let spool = Spool::new(dir.path().to_path_buf());
+        let env = vec![(MaybeUTF8::new("API_TOKEN".into()), MaybeUTF8::new("sekrit".into())),
+                       (MaybeUTF8::new("HOME".into()),      MaybeUTF8::new("/home/test".into()))];
+        let req = serve::CreateRunReq { user: "test-user".into(), name: "Spooled".into(), id: None, cmd: "true".into(), env, start: Some(1234), attempt_of: None, scheduled: None };
+        let run = spool.create_run(&req, None).unwrap();
+        let spooled = read_json::<serve::CreateRunReq>(&run.dir.join("run.json")).unwrap().unwrap();
+        assert_eq!(spooled.env, vec![(MaybeUTF8::new("API_TOKEN".into()), MaybeUTF8::new("[REDACTED]".into())),
+                                     (MaybeUTF8::new("HOME".into()),      MaybeUTF8::new("/home/test".into()))]);
+    }
+}
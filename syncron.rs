@@ -9,18 +9,31 @@ use std::error::Error;
 
 use docopt::Docopt;
 
+mod auth;
 mod client;
 mod serve;
 mod db;
 mod event;
 mod maybe_utf8;
+mod redact;
+mod spool;
 
 const USAGE: &'static str = r#"
 Usage:
   syncron --help
   syncron -c <job-cmd>
-  syncron [-h] [-v...] exec (-n <name> | -i <id> | -n <name> -i <id>) [--timeout=<timespec>] [--server=<server-url>] <job-cmd>...
+  syncron [-h] [-v...] exec (-n <name> | -i <id> | -n <name> -i <id>) [--timeout=<timespec>] [--kill-after=<timespec>] [--server=<server-url>] [--spool=<path>] [--exit-status=<mode>] [--tee=<when>] [--retries=<n>] [--retry-delay=<timespec>] [--splay=<timespec>] [--stable-splay] <job-cmd>...
+  syncron [-h] [-v...] flush [--server=<server-url>] [--spool=<path>]
+  syncron [-h] [-v...] control <message>...
+  syncron [-h] [-v...] metric <metric>...
   syncron [-h] [-v...] serve [--db=<path>] [--port=<port>]
+  syncron [-h] [-v...] token create <token-name> [--user=<user>] [--namespace=<prefix>] [--db=<path>]
+  syncron [-h] [-v...] token list [--db=<path>]
+  syncron [-h] [-v...] token revoke <token-name> [--db=<path>]
+  syncron [-h] [-v...] account create <account-name> [--role=<role>] [--see=<user>...] [--db=<path>]
+  syncron [-h] [-v...] account list [--db=<path>]
+  syncron [-h] [-v...] account passwd <account-name> [--db=<path>]
+  syncron [-h] [-v...] account delete <account-name> [--db=<path>]
 
 Options:
   -h --help              Show this message.
@@ -31,15 +44,45 @@ Options:
                          To specify the job id from the environment, set SYNCRON_NAME to
                          "@<job-id>" or "@<job-id> <job-name>".
   --timeout=<timespec>   Time out job if it runs too long. Timespec is '1s, 3m, 4h', etc.
+  --kill-after=<timespec>  How long a timed out job gets to clean up after SIGTERM before it
+                         gets SIGKILL [default: 30s]
   --server=<server-url>  Base URL of a `syncron serve` instance (env: SYNCRON_SERVER)
+  --spool=<path>         Where to keep runs when the server can't be reached. Defaults to
+                         ~/.syncron/spool (env: SYNCRON_SPOOL)
+  --exit-status=<mode>   'child' to exit with the job's exit status, or 'always-zero' to exit 0
+                         no matter how the job went [default: child] (env: SYNCRON_EXIT_STATUS)
+  --tee=<when>           Also copy the job's output to our stdout/stderr: 'always', 'never', or
+                         'on-failure' (only once the job fails) [default: never] (env: SYNCRON_TEE)
+  --retries=<n>          Try the job up to this many more times if it fails. Defaults to the job's
+                         setting on the server, or 0 (env: SYNCRON_RETRIES)
+  --retry-delay=<timespec>  How long to wait between attempts. Defaults to the job's setting on the
+                         server, or 1m (env: SYNCRON_RETRY_DELAY)
+  --splay=<timespec>     Wait a random amount of time, up to this long, before starting the job
+                         (env: SYNCRON_SPLAY)
+  --stable-splay         Make the --splay wait the same every time for this host and job
+                         (env: SYNCRON_STABLE_SPLAY=1)
   --db=<path-to-db>      Path to the db. Will be created if it doesn't exist [default: ./db]
                          (env: SYNCRON_DB)
   --port=<port>          Port to listen on [default: 8000] (env: SYNCRON_PORT)
+  --user=<user>          Only let the token be used for this user's jobs
+  --namespace=<prefix>   Only let the token be used for jobs whose ids start with this (one per host,
+                         for instance)
+  --role=<role>          What the account can do: 'viewer' (look), 'operator' (also change job settings
+                         and prune) or 'admin' (also change global settings) [default: viewer]
+  --see=<user>           Only let the account see this syncron user's jobs. Can be given more than once.
 
 Commands:
 
   syncron exec           Launch a new run for a job, uploading the run data to the Syncron server
+  syncron flush          Upload runs that were spooled while the Syncron server was unreachable
+  syncron control        From inside a job, tell the `syncron exec` running it something, like
+                         "progress 42/100" or "stage Uploading"
+  syncron metric         From inside a job, record numbers about the run, like "rows=1234"
   syncron serve          Start a Syncron server in the foreground.
+  syncron token          Manage the API tokens clients use to talk to the server. Once there are any,
+                         clients need one (env: SYNCRON_TOKEN, or in ~/.syncron/token)
+  syncron account        Manage the accounts people log in to the web interface with. Once there are any,
+                         you have to log in. The password is read from stdin.
 "#;
 
 #[derive(Debug, serde::Deserialize)]
@@ -49,12 +92,38 @@ struct Args {
     flag_port:    u16,
     flag_c:       Option<String>,
     flag_timeout: Option<String>,
+    flag_kill_after: String,
     flag_name:    Option<String>,
     flag_id:      Option<String>,
     flag_server:  Option<String>,
+    flag_spool:   Option<String>,
+    flag_exit_status: String,
+    flag_tee:     String,
+    flag_retries: Option<String>,
+    flag_retry_delay: Option<String>,
+    flag_splay:   Option<String>,
+    flag_stable_splay: bool,
     cmd_exec:     bool,
+    cmd_flush:    bool,
+    cmd_control:  bool,
+    cmd_metric:   bool,
     cmd_serve:    bool,
+    cmd_token:    bool,
+    cmd_create:   bool,
+    cmd_list:     bool,
+    cmd_revoke:   bool,
+    cmd_account:  bool,
+    cmd_passwd:   bool,
+    cmd_delete:   bool,
+    flag_user:    Option<String>,
+    flag_namespace: Option<String>,
+    arg_token_name: Option<String>,
+    flag_role:    String,
+    flag_see:     Vec<String>,
+    arg_account_name: Option<String>,
     arg_job_cmd:  Vec<String>,
+    arg_message:  Vec<String>,
+    arg_metric:   Vec<String>,
 }
 
 #[rocket::main]
@@ -66,6 +135,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // Client settings
     env_if("SYNCRON_SERVER", |s| Ok(args.flag_server = Some(s.into())))?;
+    env_if("SYNCRON_SPOOL",  |s| Ok(args.flag_spool  = Some(s.into())))?;
+    env_if("SYNCRON_EXIT_STATUS", |s| { args.flag_exit_status = s.into(); Ok(()) })?;
+    env_if("SYNCRON_TEE",    |s| { args.flag_tee = s.into(); Ok(()) })?;
+    env_if("SYNCRON_RETRIES", |s| { args.flag_retries = Some(s.into()); Ok(()) })?;
+    env_if("SYNCRON_RETRY_DELAY", |s| { args.flag_retry_delay = Some(s.into()); Ok(()) })?;
+    env_if("SYNCRON_SPLAY",  |s| { args.flag_splay = Some(s.into()); Ok(()) })?;
+    env_if("SYNCRON_STABLE_SPLAY", |s| { args.flag_stable_splay = !matches!(s, "" | "0"); Ok(()) })?;
     env_if("SYNCRON_NAME",   |s| Ok({ let (job_id, name) = parse_name_env(s);
                                       if job_id.is_some() { args.flag_id     = job_id }
                                       if name.is_some()   { args.flag_name   = name } }))?;
@@ -102,16 +178,134 @@ async fn main() -> Result<(), Box<dyn Error>> {
         let server: reqwest::Url = args.flag_server.ok_or("missing --server or SYNCRON_SERVER environment variable")?.parse()?;
         let name   = args.flag_name  .ok_or("missing --name or SYNCRON_NAME environment variable")?;
         let timeout = args.flag_timeout.map(|s| parse_timespec(&s).unwrap());
-        let result = client::Job::new(server.clone(), &getuser(), &name, args.flag_id.as_deref(), timeout, &job_cmd).await.map_err(|e| e.to_string());
-        match result {
-            Ok(job) => {
+        let kill_after = parse_timespec(&args.flag_kill_after)?;
+        let always_zero = match args.flag_exit_status.as_str() {
+            "child"       => false,
+            "always-zero" => true,
+            mode          => Err(format!("Bad --exit-status: {}", mode))?,
+        };
+        let tee: client::Tee = args.flag_tee.parse()?;
+        let retries = args.flag_retries.as_deref().map(|n| n.parse::<u32>().map_err(|e| wrap(e, "Bad --retries"))).transpose()?;
+        let retry_delay = args.flag_retry_delay.as_deref().map(parse_timespec).transpose()?;
+        let spool = spool::Spool::new(args.flag_spool.map(|s| s.into()).unwrap_or_else(spool::default_dir));
+        let mut req = client::Job::create_req(&getuser(), &name, args.flag_id.as_deref(), &job_cmd);
+        if let Some(ref max) = args.flag_splay {
+            req.scheduled = Some(chrono::Local::now().timestamp_millis());
+            let delay = client::splay(parse_timespec(max)?, args.flag_stable_splay.then(|| args.flag_id.as_deref().unwrap_or(&name)))?;
+            info!("Waiting {:?} before starting (--splay)", delay);
+            tokio::time::sleep(delay).await;
+        }
+        let result = client::Job::new(server.clone(), req.clone(), timeout, kill_after, tee, &job_cmd).await.map_err(|e| e.to_string());
+        let status = match result {
+            Ok(None) => {
+                info!("A previous run of {} is still going. Skipping this one.", name);
+                db::ExitStatus::Skipped
+            },
+            Ok(Some(mut job)) => {
                 trace!("{:?}", job);
-                job.run().await?;
+                let retries = retries.or(job.retry.retries).unwrap_or(0);
+                let retry_delay = retry_delay.unwrap_or_else(|| std::time::Duration::from_secs(job.retry.delay.unwrap_or(60)));
+                let mut status = job.run().await?;
+                for attempt in 1..=retries {
+                    if !client::retryable(status, &job.success) { break }
+                    info!("Attempt {} of {} failed ({:?}). Trying again in {:?}", attempt, retries+1, status, retry_delay);
+                    if let Some(signal) = client::retry_delay(retry_delay).await? {
+                        status = db::ExitStatus::Interrupted(signal);
+                        break;
+                    }
+                    job = job.retry().await?;
+                    status = job.run().await?;
+                }
+                // The server is obviously up, so this is a good time to upload anything left over from when it wasn't.
+                let api = client::Api::new(server)?;
+                if let Err(e) = spool.flush(&api).await {
+                    warn!("Couldn't flush spooled runs: {}", e);
+                }
+                status
             },
             Err(e) => {
                 warn!("Failed to connect to server {}: {}. Running job in fallback mode.", server, e);
-                client::fallback_run(timeout, &job_cmd).await?;
+                let spool_run = |first: Option<&spool::SpoolRun>| match spool.create_run(&serve::CreateRunReq { start: Some(chrono::Local::now().timestamp_millis()), ..req.clone() }, first) {
+                    Ok(spooled) => Some(spooled),
+                    Err(e) => { warn!("Couldn't spool run, it won't make it to the server: {}", e); None },
+                };
+                // Without the server there are no job settings, so it's only retried if --retries says so, and whether
+                // an attempt failed goes by the default success policy. Holding on to the first attempt keeps it locked
+                // so it can't be flushed before the retries are tied to it.
+                let (retries, retry_delay, success) = (retries.unwrap_or(0), retry_delay.unwrap_or(std::time::Duration::from_secs(60)), db::SuccessPolicy::default());
+                let first = spool_run(None);
+                let mut status = client::fallback_run(timeout, kill_after, &job_cmd, first.as_ref()).await?;
+                for attempt in 1..=retries {
+                    if !client::retryable(status, &success) { break }
+                    info!("Attempt {} of {} failed ({:?}). Trying again in {:?}", attempt, retries+1, status, retry_delay);
+                    if let Some(signal) = client::retry_delay(retry_delay).await? {
+                        status = db::ExitStatus::Interrupted(signal);
+                        break;
+                    }
+                    let retry = spool_run(first.as_ref());
+                    status = client::fallback_run(timeout, kill_after, &job_cmd, retry.as_ref()).await?;
+                }
+                status
+            }
+        };
+        if !always_zero {
+            exit_like(status);
+        }
+    } else if args.cmd_flush {
+        let server: reqwest::Url = args.flag_server.ok_or("missing --server or SYNCRON_SERVER environment variable")?.parse()?;
+        let spool = spool::Spool::new(args.flag_spool.map(|s| s.into()).unwrap_or_else(spool::default_dir));
+        let api = client::Api::new(server)?;
+        let flushed = spool.flush(&api).await?;
+        info!("Flushed {} spooled runs", flushed);
+    } else if args.cmd_control {
+        client::send_control(&args.arg_message.join(" "))?;
+    } else if args.cmd_metric {
+        for metric in args.arg_metric.iter() {
+            client::send_control(&format!("metric {}", metric))?;
+        }
+    }
+
+    if args.cmd_token {
+        let db = db::Db::new(&std::path::PathBuf::from(args.flag_db.clone())).await?;
+        if args.cmd_create {
+            let name = args.arg_token_name.as_deref().expect("docopt makes sure");
+            let (_token, secret) = auth::Token::create(&db, name, args.flag_user.as_deref(), args.flag_namespace.as_deref()).await?;
+            println!("{}", secret);
+            eprintln!("Put that in SYNCRON_TOKEN or ~/.syncron/token on the client. It can't be shown again.");
+        } else if args.cmd_list {
+            let local = |t: chrono::DateTime<chrono::Local>| t.format("%Y-%m-%d %H:%M").to_string();
+            println!("{:<20} {:<30} {:<16} {:<16} REVOKED", "NAME", "SCOPE", "CREATED", "LAST USED");
+            for token in auth::Token::list(&db).await? {
+                let scope = [token.user.map(|u| format!("user={}", u)), token.namespace.map(|n| format!("namespace={}", n))]
+                              .into_iter().flatten().collect::<Vec<_>>().join(" ");
+                println!("{:<20} {:<30} {:<16} {:<16} {}", token.name, if scope.is_empty() { "(any)".into() } else { scope },
+                         local(token.created), token.last_used.map(local).unwrap_or("never".into()), token.revoked.map(local).unwrap_or_default());
             }
+        } else if args.cmd_revoke {
+            let name = args.arg_token_name.as_deref().expect("docopt makes sure");
+            if !auth::Token::revoke(&db, name).await? { Err(format!("No token named {:?} (or it was already revoked)", name))? }
+        }
+    }
+
+    if args.cmd_account {
+        let db = db::Db::new(&std::path::PathBuf::from(args.flag_db.clone())).await?;
+        let name = args.arg_account_name.as_deref().unwrap_or_default();
+        if args.cmd_create {
+            let (role, password): (auth::Role, _) = (args.flag_role.parse()?, read_password()?);
+            auth::Account::create(&db, name, &password, role, &args.flag_see).await?;
+        } else if args.cmd_list {
+            let local = |t: chrono::DateTime<chrono::Local>| t.format("%Y-%m-%d %H:%M").to_string();
+            println!("{:<20} {:<10} {:<30} {:<16} LAST LOGIN", "NAME", "ROLE", "SEES", "CREATED");
+            for account in auth::Account::list(&db).await? {
+                println!("{:<20} {:<10} {:<30} {:<16} {}", account.name, account.role,
+                         if account.users.is_empty() { "(everything)".into() } else { account.users.join(" ") },
+                         local(account.created), account.last_login.map(local).unwrap_or("never".into()));
+            }
+        } else if args.cmd_passwd {
+            let password = read_password()?;
+            if !auth::Account::set_password(&db, name, &password).await? { Err(format!("No account named {:?}", name))? }
+        } else if args.cmd_delete {
+            if !auth::Account::delete(&db, name).await? { Err(format!("No account named {:?}", name))? }
         }
     }
 
@@ -124,6 +318,52 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+// Exit the same way the job did so whatever ran us (cron, systemd, a wrapper script) can tell how it went.
+fn exit_like(status: db::ExitStatus) -> ! {
+    use nix::sys::signal::{self, SigHandler, Signal};
+    match status {
+        db::ExitStatus::Exited(code) => std::process::exit(code),
+        db::ExitStatus::Signal(sig) | db::ExitStatus::CoreDump(sig) | db::ExitStatus::Interrupted(sig) | db::ExitStatus::Superseded(sig) => {
+            if let Ok(signal) = Signal::try_from(sig) {
+                // The job already left a core file if it was going to, we don't need to leave one of our own.
+                _ = nix::sys::resource::setrlimit(nix::sys::resource::Resource::RLIMIT_CORE, 0, 0);
+                _ = unsafe { signal::signal(signal, SigHandler::SigDfl) };
+                _ = signal::raise(signal);
+            }
+            std::process::exit(128 + sig) // The signal didn't kill us (SIGCHLD, say). This is what shells do.
+        },
+        db::ExitStatus::ClientTimeout(_) => std::process::exit(124), // Same as timeout(1)
+        db::ExitStatus::ServerTimeout => std::process::exit(1),   // Only the server decides this, so we shouldn't ever see it
+        db::ExitStatus::Skipped => std::process::exit(0),         // Not running was the plan
+    }
+}
+
+// Asks twice (without echoing) when stdin is a terminal, otherwise just reads a line so it can be scripted.
+fn read_password() -> Result<String, Box<dyn Error>> {
+    use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+    let read = |prompt: &str| -> Result<String, Box<dyn Error>> {
+        let saved = tcgetattr(0).ok(); // Fails if stdin isn't a terminal
+        if let Some(ref saved) = saved {
+            eprint!("{}", prompt);
+            let mut quiet = saved.clone();
+            quiet.local_flags.remove(LocalFlags::ECHO);
+            tcsetattr(0, SetArg::TCSANOW, &quiet)?;
+        }
+        let mut line = String::new();
+        let result = std::io::stdin().read_line(&mut line);
+        if let Some(ref saved) = saved {
+            tcsetattr(0, SetArg::TCSANOW, saved)?;
+            eprintln!();
+        }
+        result?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    };
+    let password = read("Password: ")?;
+    if password.is_empty() { Err("The password can't be empty")? }
+    if tcgetattr(0).is_ok() && read("Again: ")? != password { Err("The passwords didn't match")? }
+    Ok(password)
+}
+
 fn wrap<E: Error>(e: E, s: &str) -> Box<dyn Error> {
     Box::<dyn Error>::from(format!("{}: {:?}", s, e))
 }
@@ -92,6 +92,7 @@ function use_retention_state() {
 
 export function global_settings({jobs, close_settings}) {
     let [retention, into_retention_state, from_retention_state, set_retention_path] = use_retention_state();
+    let [redaction, set_redaction] = React.useState(null);
     let [save_state, set_save_state] = React.useState();
 
     React.useEffect(() => {
@@ -100,13 +101,16 @@ export function global_settings({jobs, close_settings}) {
             let settings = (await fetch_json("/settings"));
             if (cancelled) return;
             into_retention_state(settings.retention);
+            set_redaction(settings.redaction);
         })();
         return () => cancelled = true;
     }, ["/settings"]);
 
     const save_settings = async () => await _fetch("/settings", {
         method: "PUT",
-        body: JSON.stringify({ retention: from_retention_state() })
+        body: JSON.stringify({ retention: from_retention_state(),
+                               redaction: redaction && { ...redaction, names:  redaction.names.filter(s => s.trim() != ""),
+                                                                       values: redaction.values.filter(s => s.trim() != "") } })
     });
 
     const prune_dry_run = React.useCallback(async () => {
@@ -127,12 +131,35 @@ export function global_settings({jobs, close_settings}) {
 
     return jsr([settings_modal, { kind: "global settings", title: 'Settings', save_settings, close_settings },
                 retention == null ? [loading] : [React.Fragment,
-                                                 [global_retention_settings, { retention, set_retention_path, prune_dry_run }]],
+                                                 [global_retention_settings, { retention, set_retention_path, prune_dry_run }],
+                                                 redaction && [redaction_settings, { redaction, set_redaction }]],
+               ]);
+}
+
+function redaction_settings({redaction, set_redaction}) {
+    const lines_field = (key, label) => ['div', { className: 'mb-3' },
+                                         ['label', { className: 'form-label', for: `redaction-${key}` }, label],
+                                         ['textarea', { className: 'form-control', id: `redaction-${key}`, rows: 4, value: redaction[key].join("\n"),
+                                                        onChange: (e) => set_redaction(prev => ({ ...prev, [key]: e.target.value.split("\n") })) }]];
+    return jsr(['div', { className: "redaction" },
+                ['h2', "Redaction" ],
+                ['p', "Keeps secrets in jobs' environments from being stored or shown. One per line."],
+                lines_field('names',  'Variable names (globs, case insensitive). Their whole value is redacted.'),
+                lines_field('values', 'Value patterns (regexes). Only the matching part is redacted.'),
+                ['div', { className: 'form-check' },
+                 ['input', { className: 'form-check-input', type: 'checkbox', id: 'redaction-logs', checked: redaction.logs,
+                             onChange: (e) => set_redaction(prev => ({ ...prev, logs: e.target.checked })) }],
+                 ['label', { className: 'form-check-label', for: 'redaction-logs' }, 'Mask them in job output, too']],
                ]);
 }
 
 export function job_settings({job, close_settings}) {
     let [retention, into_retention_state, from_retention_state, set_retention_path] = use_retention_state();
+    let [overlap, set_overlap] = React.useState("allow");
+    let [retry, set_retry] = React.useState({});
+    let [success, set_success] = React.useState(default_success_policy);
+    let [log_rules, set_log_rules] = React.useState([]);
+    let [reevaluate, set_reevaluate] = React.useState(false);
     let [save_state, set_save_state] = React.useState();
 
     React.useEffect(() => {
@@ -141,20 +168,45 @@ export function job_settings({job, close_settings}) {
             let settings = (await fetch_json(job.settings_url));
             if (cancelled) return;
             into_retention_state(settings.retention);
+            set_overlap(settings.overlap ?? "allow");
+            set_retry(settings.retry ?? {});
+            set_success(settings.success ?? default_success_policy);
+            set_log_rules(settings.log_rules ?? []);
         })();
         return () => cancelled = true;
     }, [job.runs_url]);
 
-    const save_settings = async () => await _fetch(job.settings_url, {
-        method: "PUT",
-        body: JSON.stringify({ retention: from_retention_state() }),
-    });
+    const save_settings = async () => {
+        await _fetch(job.settings_url, {
+            method: "PUT",
+            body: JSON.stringify({ retention: from_retention_state(), overlap, retry, success, log_rules: log_rules.filter(rule => rule.pattern != "") }),
+        });
+        if (reevaluate)
+            await _fetch(job.success_url, { method: "POST" });
+    };
 
     const prune_dry_run = async () => await fetch_json(url_with(job.prune_url, { settings: JSON.stringify(from_retention_state()) }));
 
     return jsr([settings_modal, { kind: "job settings", title: `Job Settings for ${job.user} / ${job.name}`, save_settings, close_settings },
                  retention == null ? [loading] : [React.Fragment,
-                                                  [job_retention_settings, { retention, set_retention_path, prune_dry_run }]],
+                                                  [job_retention_settings, { retention, set_retention_path, prune_dry_run }],
+                                                  [job_overlap_settings, { overlap, set_overlap }],
+                                                  [job_retry_settings, { retry, set_retry }],
+                                                  [job_success_settings, { success, set_success, reevaluate, set_reevaluate }],
+                                                  [job_log_rules_settings, { log_rules, set_log_rules }]],
+               ]);
+}
+
+function job_overlap_settings({overlap, set_overlap}) {
+    return jsr(['div', { className: "overlap" },
+                ['h2', "Overlapping Runs" ],
+                ['p', "What to do when the job starts while a previous run is still going:"],
+                ['select', { className: 'form-select', 'aria-label': 'Overlapping runs', value: overlap,
+                             onChange: (e) => set_overlap(e.target.value) },
+                 ['option', { value: 'allow' },         'Let them both run'],
+                 ['option', { value: 'skip' },          'Skip the new run'],
+                 ['option', { value: 'queue' },         'Start the new run once the previous one finishes'],
+                 ['option', { value: 'kill_previous' }, 'Stop the previous run and then start the new one']],
                ]);
 }
 
@@ -176,6 +228,65 @@ function global_retention_settings({retention, set_retention_path, prune_dry_run
                ]);
 }
 
+function job_retry_settings({retry, set_retry}) {
+    // Blank means "not set", which lets the client's --retries/--retry-delay (or their defaults) decide.
+    const number_field = (key, label) => ['div', { className: 'input-group' },
+                                          ['span', { className: 'input-group-text' }, label],
+                                          ['input', { type: 'number', min: 0, className: 'form-control', value: retry[key] ?? "",
+                                                      onChange: (e) => set_retry(prev => ({ ...prev, [key]: e.target.value === "" ? undefined : e.target.value * 1 })) }]];
+    return jsr(['div', { className: "retry" },
+                ['h2', "Retries" ],
+                ['p', "If the job fails, the client can try it again. Each attempt is recorded as its own run."],
+                number_field('retries', 'Retries'),
+                number_field('delay',   'Seconds between attempts'),
+               ]);
+}
+
+const default_success_policy = { exit_codes: [0], warning_codes: [], empty_output: true, signals: false, timeout_fails: true };
+
+function job_success_settings({success, set_success, reevaluate, set_reevaluate}) {
+    let [typed, set_typed] = React.useState({}); // What they typed, so it doesn't get reformatted out from under them
+    const codes_field = (key, label) => ['div', { className: 'input-group' },
+                                         ['span', { className: 'input-group-text' }, label],
+                                         ['input', { type: 'text', className: 'form-control', value: typed[key] ?? (success[key] ?? []).join(" "),
+                                                     onChange: (e) => { set_typed(prev => ({ ...prev, [key]: e.target.value }));
+                                                                        set_success(prev => ({ ...prev, [key]: e.target.value.split(/[\s,]+/).filter(c => c != "" && !isNaN(c)).map(c => c * 1) })) } }]];
+    const checkbox = (key, label) => ['div', { className: 'form-check' },
+                                      ['input', { className: 'form-check-input', type: 'checkbox', id: `success-${key}`, checked: success[key],
+                                                  onChange: (e) => set_success(prev => ({ ...prev, [key]: e.target.checked })) }],
+                                      ['label', { className: 'form-check-label', for: `success-${key}` }, label]];
+    return jsr(['div', { className: "success" },
+                ['h2', "Success" ],
+                codes_field('exit_codes',    'Successful exit codes'),
+                codes_field('warning_codes', 'Exit codes that mean "finished, with warnings"'),
+                checkbox('empty_output',  'Any other exit code is fine if the job printed nothing'),
+                checkbox('signals',       'Getting killed by a signal is fine'),
+                checkbox('timeout_fails', 'Timing out is a failure'),
+                ['div', { className: 'form-check' },
+                 ['input', { className: 'form-check-input', type: 'checkbox', id: 'success-reevaluate', checked: reevaluate,
+                             onChange: (e) => set_reevaluate(e.target.checked) }],
+                 ['label', { className: 'form-check-label', for: 'success-reevaluate' }, 'Re-check past runs when saving']],
+               ]);
+}
+
+function job_log_rules_settings({log_rules, set_log_rules}) {
+    const update = (i, changes) => set_log_rules(prev => prev.map((rule, j) => i == j ? { ...rule, ...changes } : rule));
+    return jsr(['div', { className: "log-rules" },
+                ['h2', "Log Rules" ],
+                ['p', "Regexes checked against each line of the output when a run finishes. The run gets marked at the lines that match."],
+                log_rules.map((rule, i) =>
+                    ['div', { className: 'input-group', key: i },
+                     ['input', { type: 'text', className: 'form-control', placeholder: 'Regex', value: rule.pattern,
+                                 onChange: (e) => update(i, { pattern: e.target.value }) }],
+                     ['select', { className: 'form-select', 'aria-label': 'Level', value: rule.level,
+                                  onChange: (e) => update(i, { level: e.target.value }) },
+                      ['option', { value: 'warning' }, 'Warning'],
+                      ['option', { value: 'failure' }, 'The run failed']],
+                     ['button', { className: 'btn btn-outline-secondary', onClick: prevent_default(() => set_log_rules(prev => prev.filter((_, j) => i != j))) }, 'Remove']]),
+                ['button', { className: 'btn btn-outline-secondary', onClick: prevent_default(() => set_log_rules(prev => [...prev, { pattern: "", level: "failure" }])) }, 'Add rule'],
+               ]);
+}
+
 const Loading = Symbol("Loading");
 function core_retention_settings({retention, set_retention_path, prune_dry_run}) {
     let [prune_dry_run_stats, set_prune_dry_run_stats] = React.useState(undefined);
@@ -1,6 +1,6 @@
 // Copyright © 2022-2024 David Caldwell <david@porkrind.org>
 import { React, ReactDOM, jsr } from "./lib/jsml-react-bundle.js"
-import { loading, card, prevent_default, human_bytes, url_with, fetch_json, fetch_text } from "./utils.js"
+import { loading, card, prevent_default, human_bytes, url_with, _fetch, fetch_json, fetch_text } from "./utils.js"
 import { global_settings, job_settings, Saved } from "./settings.js"
 import { prune_modal } from "./prune.js"
 
@@ -13,6 +13,19 @@ window.onload = main;
 
 function app({nav_el, initial_view}) {
     let [view, set_view] = React.useState(initial_view || {view: "jobs"});
+    let [account, set_account] = React.useState(undefined); // null means there aren't any accounts, so no logging in
+
+    React.useEffect(() => {
+        (async () => {
+            let resp = await window.fetch("/account");
+            set_account(resp.status == 401 ? { logged_out: true } : await resp.json());
+        })();
+    }, []);
+
+    const logout = async () => {
+        await _fetch("/logout", { method: "POST" });
+        set_account({ logged_out: true });
+    };
 
     const push_view = (view) => {
         history.pushState(view, "", view.view == "jobs" ? '#' :
@@ -46,9 +59,13 @@ function app({nav_el, initial_view}) {
                    crumbs.map((crumb) =>
                        crumb.click ? ["li", { className: "breadcrumb-item" },        ["a", { href:"#", onClick:prevent_default(crumb.click) }, crumb.id]]
                                    : ["li", { className: "breadcrumb-item active" },                                                           crumb.id]),
-                  ]]],
-                view.view == "jobs" ? [jobs_view, { set_view: push_view, jobs_url: "/jobs", runs_url: "/runs" }] :
-                view.view == "runs" ? [runs_view, { set_view: push_view, runs_url: view.runs_url, job: view.job }] :
+                  ]],
+                 account?.name && ["div", { className: "account" },
+                                   `${account.name} (${account.role}) `, ["a", { href: "#", onClick: prevent_default(logout) }, "Log out"]]],
+                account === undefined ? [loading] :
+                account.logged_out    ? [login_view, { set_account }] :
+                view.view == "jobs" ? [jobs_view, { set_view: push_view, jobs_url: "/jobs", runs_url: "/runs", account, deleted: view.deleted }] :
+                view.view == "runs" ? [runs_view, { set_view: push_view, runs_url: view.runs_url, job: view.job, account }] :
                 view.view == "log"  ? [log_view,  { set_view: push_view, run_url:  view.run_url,  job: view.job, run_id: view.run_id }]
                                     : ["div", { className: "alert alert-danger" }, "Can't happen"]]);
 }
@@ -57,11 +74,47 @@ function nav({el, children}) {
     return ReactDOM.createPortal(children, el);
 }
 
+function login_view({set_account}) {
+    let [name, set_name] = React.useState("");
+    let [password, set_password] = React.useState("");
+    let [error, set_error] = React.useState(undefined);
+
+    const login = async () => {
+        let resp = await window.fetch("/login", { method: "POST", headers: { "Content-Type": "application/json" },
+                                                  body: JSON.stringify({ name, password }) });
+        if (resp.ok)
+            return set_account(await resp.json());
+        set_error(resp.status == 401 ? "Wrong name or password" : `Couldn't log in: ${resp.statusText}`);
+    };
+
+    return jsr([card, { kind: "login", title: "Log In" },
+                ["form", { onSubmit: prevent_default(login) },
+                 error && ["div", { className: "alert alert-danger" }, error],
+                 ["div", { className: "mb-3" },
+                  ["label", { className: "form-label", for: "login-name" }, "Name"],
+                  ["input", { className: "form-control", id: "login-name", autoComplete: "username", autoFocus: true,
+                              value: name, onChange: (e) => set_name(e.target.value) }]],
+                 ["div", { className: "mb-3" },
+                  ["label", { className: "form-label", for: "login-password" }, "Password"],
+                  ["input", { className: "form-control", id: "login-password", type: "password", autoComplete: "current-password",
+                              value: password, onChange: (e) => set_password(e.target.value) }]],
+                 ["button", { type: "submit" }, "Log In"]]]);
+}
+
+const roles = ["viewer", "operator", "admin"];
+
+// Without an account (there aren't any on the server) anyone can do anything
+function can(account, role) {
+    return !account || roles.indexOf(account.role) >= roles.indexOf(role);
+}
+
 const svg = {
     Success: ["svg", { xmlns: "http://www.w3.org/2000/svg", width: "32", height: "32", fill: "currentColor", className: "bi bi-check-circle-fill text-success", viewBox: "0 0 16 16" },
               ["path", { d: "M16 8A8 8 0 1 1 0 8a8 8 0 0 1 16 0zm-3.97-3.03a.75.75 0 0 0-1.08.022L7.477 9.417 5.384 7.323a.75.75 0 0 0-1.06 1.06L6.97 11.03a.75.75 0 0 0 1.079-.02l3.992-4.99a.75.75 0 0 0-.01-1.05z" }]],
     Failure: ["svg", { xmlns: "http://www.w3.org/2000/svg", width: "32", height: "32", fill: "currentColor", className: "bi bi-exclamation-triangle-fill text-danger", viewBox: "0 0 16 16" },
               ["path", { d: "M8.982 1.566a1.13 1.13 0 0 0-1.96 0L.165 13.233c-.457.778.091 1.767.98 1.767h13.713c.889 0 1.438-.99.98-1.767L8.982 1.566zM8 5c.535 0 .954.462.9.995l-.35 3.507a.552.552 0 0 1-1.1 0L7.1 5.995A.905.905 0 0 1 8 5zm.002 6a1 1 0 1 1 0 2 1 1 0 0 1 0-2z" }]],
+    Warning: ["svg", { xmlns: "http://www.w3.org/2000/svg", width: "32", height: "32", fill: "currentColor", className: "bi bi-exclamation-circle-fill text-warning", viewBox: "0 0 16 16" },
+              ["path", { d: "M16 8A8 8 0 1 1 0 8a8 8 0 0 1 16 0M8 4a.905.905 0 0 0-.9.995l.35 3.507a.552.552 0 0 0 1.1 0l.35-3.507A.905.905 0 0 0 8 4m.002 6a1 1 0 1 0 0 2 1 1 0 0 0 0-2" }]],
     Running: ["svg", { xmlns: "http://www.w3.org/2000/svg", width: "32", height: "32", fill: "currentColor", className: "bi bi-hypnotize text-info", viewBox: "0 0 16 16" },
               ["path", { d: "m7.949 7.998.006-.003.003.009-.01-.006Zm.025-.028v-.03l.018.01-.018.02Zm0 .015.04-.022.01.006v.04l-.029.016-.021-.012v-.028Zm.049.057v-.014l-.008.01.008.004Zm-.05-.008h.006l-.006.004v-.004Z" }],
               ["path", { fillRule: "evenodd", d: "M8 0a8 8 0 1 0 0 16A8 8 0 0 0 8 0ZM4.965 1.69a6.972 6.972 0 0 1 3.861-.642c.722.767 1.177 1.887 1.177 3.135 0 1.656-.802 3.088-1.965 3.766 1.263.24 2.655-.815 3.406-2.742.38-.975.537-2.023.492-2.996a7.027 7.027 0 0 1 2.488 3.003c-.303 1.01-1.046 1.966-2.128 2.59-1.44.832-3.09.85-4.26.173l.008.021.012-.006-.01.01c.42 1.218 2.032 1.9 4.08 1.586a7.415 7.415 0 0 0 2.856-1.081 6.963 6.963 0 0 1-1.358 3.662c-1.03.248-2.235.084-3.322-.544-1.433-.827-2.272-2.236-2.279-3.58l-.012-.003c-.845.972-.63 2.71.666 4.327a7.415 7.415 0 0 0 2.37 1.935 6.972 6.972 0 0 1-3.86.65c-.727-.767-1.186-1.892-1.186-3.146 0-1.658.804-3.091 1.969-3.768l-.002-.007c-1.266-.25-2.666.805-3.42 2.74a7.415 7.415 0 0 0-.49 3.012 7.026 7.026 0 0 1-2.49-3.018C1.87 9.757 2.613 8.8 3.696 8.174c1.438-.83 3.084-.85 4.253-.176l.005-.006C7.538 6.77 5.924 6.085 3.872 6.4c-1.04.16-2.03.55-2.853 1.08a6.962 6.962 0 0 1 1.372-3.667l-.002.003c1.025-.243 2.224-.078 3.306.547 1.43.826 2.269 2.23 2.28 3.573L8 7.941c.837-.974.62-2.706-.673-4.319a7.415 7.415 0 0 0-2.362-1.931Z" }]],
@@ -71,20 +124,33 @@ const svg = {
     Settings: ['svg', { xmlns:'http://www.w3.org/2000/svg',width:'16',height:'16',fill:'currentColor',className:'bi bi-gear',viewBox:'0 0 16 16' },
                ['path', { d:'M8 4.754a3.246 3.246 0 1 0 0 6.492 3.246 3.246 0 0 0 0-6.492M5.754 8a2.246 2.246 0 1 1 4.492 0 2.246 2.246 0 0 1-4.492 0' }],
                ['path', { d:'M9.796 1.343c-.527-1.79-3.065-1.79-3.592 0l-.094.319a.873.873 0 0 1-1.255.52l-.292-.16c-1.64-.892-3.433.902-2.54 2.541l.159.292a.873.873 0 0 1-.52 1.255l-.319.094c-1.79.527-1.79 3.065 0 3.592l.319.094a.873.873 0 0 1 .52 1.255l-.16.292c-.892 1.64.901 3.434 2.541 2.54l.292-.159a.873.873 0 0 1 1.255.52l.094.319c.527 1.79 3.065 1.79 3.592 0l.094-.319a.873.873 0 0 1 1.255-.52l.292.16c1.64.893 3.434-.902 2.54-2.541l-.159-.292a.873.873 0 0 1 .52-1.255l.319-.094c1.79-.527 1.79-3.065 0-3.592l-.319-.094a.873.873 0 0 1-.52-1.255l.16-.292c.893-1.64-.902-3.433-2.541-2.54l-.292.159a.873.873 0 0 1-1.255-.52zm-2.633.283c.246-.835 1.428-.835 1.674 0l.094.319a1.873 1.873 0 0 0 2.693 1.115l.291-.16c.764-.415 1.6.42 1.184 1.185l-.159.292a1.873 1.873 0 0 0 1.116 2.692l.318.094c.835.246.835 1.428 0 1.674l-.319.094a1.873 1.873 0 0 0-1.115 2.693l.16.291c.415.764-.42 1.6-1.185 1.184l-.291-.159a1.873 1.873 0 0 0-2.693 1.116l-.094.318c-.246.835-1.428.835-1.674 0l-.094-.319a1.873 1.873 0 0 0-2.692-1.115l-.292.16c-.764.415-1.6-.42-1.184-1.185l.159-.291A1.873 1.873 0 0 0 1.945 8.93l-.319-.094c-.835-.246-.835-1.428 0-1.674l.319-.094A1.873 1.873 0 0 0 3.06 4.377l-.16-.292c-.415-.764.42-1.6 1.185-1.184l.292.159a1.873 1.873 0 0 0 2.692-1.115z' }]],
+    Delete: ['svg', { xmlns:'http://www.w3.org/2000/svg',width:'16',height:'16',fill:'currentColor',className:'bi bi-trash',viewBox:'0 0 16 16' },
+             ['path', { d:'M5.5 5.5A.5.5 0 0 1 6 6v6a.5.5 0 0 1-1 0V6a.5.5 0 0 1 .5-.5m2.5 0a.5.5 0 0 1 .5.5v6a.5.5 0 0 1-1 0V6a.5.5 0 0 1 .5-.5m3 .5a.5.5 0 0 0-1 0v6a.5.5 0 0 0 1 0z' }],
+             ['path', { d:'M14.5 3a1 1 0 0 1-1 1H13v9a2 2 0 0 1-2 2H5a2 2 0 0 1-2-2V4h-.5a1 1 0 0 1-1-1V2a1 1 0 0 1 1-1H6a1 1 0 0 1 1-1h2a1 1 0 0 1 1 1h3.5a1 1 0 0 1 1 1zM4.118 4 4 4.059V13a1 1 0 0 0 1 1h6a1 1 0 0 0 1-1V4.059L11.882 4zM2.5 3h11V2h-11z' }]]
 }
 
 function human_status(status) {
     return status == void 0     ? "..." :
            'ServerTimeout' == status ? 'Timeout: Client disappeared'                :
-           'ClientTimeout' == status ? 'Timeout: Job took too long'                 :
+           'Skipped'       == status ? 'Skipped: The previous run was still going'  :
+           'ClientTimeout' in status ? `Timeout: Job took too long and was killed with ${signal_name(status.ClientTimeout)}` :
+           'Interrupted'   in status ? `Interrupted: Client got ${signal_name(status.Interrupted)} and passed it on` :
+           'Superseded'    in status ? `Superseded: Killed with ${signal_name(status.Superseded)} so a newer run could start` :
            'Exited'        in status ? `Exited with status ${status.Exited}`        :
            'Signal'        in status ? `Killed with signal ${status.Signal}`        :
            'CoreDump'      in status ? `Dumped Core with signal ${status.CoreDump}` : "???";
 }
 
+function signal_name(signal) {
+    return { 1: "SIGHUP", 2: "SIGINT", 9: "SIGKILL", 15: "SIGTERM" }[signal] ?? `signal ${signal}`;
+}
+
 function status_state(run) {
     return run.status == null                                                          ? 'Running' :
+           run.outcome != null                                                         ? { success: 'Success', warning: 'Warning', failure: 'Failure' }[run.outcome] :
+           // Older servers don't send the outcome, so guess.
            run.status.Exited != void 0 && (run.status.Exited == 0 || run.log_len == 0) ? 'Success' :
+           run.status == 'Skipped'                                                     ? 'Success' :
                                                                                          'Failure' ;
 }
 
@@ -111,16 +177,19 @@ function run_status(props) {
     return jsr([React.Fragment,
                 status != "Running" && ["span", status, ["br"],
                                         ["span", { className: "status-deets" }, human_status(props.run.status) ]],
-                status == "Running" && props.run.progress != null && [
+                props.run.attempt && [["br"], ["span", { className: "status-deets" }, `Retry: attempt ${props.run.attempt.number}`]],
+                status == "Running" && props.run.progress?.percent != null && [
                     ["div", { className: "progress" },
                      ["div", { className: "progress-bar",
                                role: "progressbar", style: { width: `${props.run.progress.percent * 100}%` }, "aria-valuenow": props.run.progress.percent * 100, "aria-valuemin": 0, "aria-valuemax": 100 }]],
-                    ["span", { className: "eta" }, `ETA: ${elapsed(props.run.progress.eta_seconds)}`]],
-                status == "Running" && props.run.progress == null && [
+                    ["span", { className: "eta" }, props.run.progress.eta_seconds != null ? `ETA: ${elapsed(props.run.progress.eta_seconds)}` : "ETA: Unknown"]],
+                status == "Running" && props.run.progress?.percent == null && [
                     ["div", { className: "progress" },
                      ["div", { className: "progress-bar indeterminate",
                                role: "progressbar", style: { width: "100%" }, "aria-valuenow": 100, "aria-valuemin": 0, "aria-valuemax": 100 }]],
-                    ["span", { className: "eta" }, "ETA: Unknown"]]]);
+                    ["span", { className: "eta" }, "ETA: Unknown"]],
+                status == "Running" && props.run.progress?.done != null && [["br"], ["span", { className: "status-deets" }, `${props.run.progress.done} done`]],
+                status == "Running" && props.run.progress?.stage && [["br"], ["span", { className: "status-deets" }, props.run.progress.stage]]]);
 }
 
 function delay(ms) {
@@ -153,8 +222,9 @@ function use_visibility(on_focus, deps) {
 }
 
 
-function jobs_view({jobs_url, runs_url, set_view}) {
+function jobs_view({jobs_url, runs_url, set_view, account, deleted}) {
     let [jobs, set_jobs] = React.useState(null);
+    let [undoable, set_undoable] = React.useState(deleted); // The job they just deleted from its runs view
     let [show_settings, set_show_settings] = React.useState(false);
     let [prune_state, set_prune_state] = React.useState(undefined);
 
@@ -184,8 +254,10 @@ function jobs_view({jobs_url, runs_url, set_view}) {
                     return new_jobs;
                 });
             };
-            if ("job_create" in event)
-                set_jobs(old_jobs => old_jobs.concat([event.job_create]));
+            if ("job_create" in event) // An undeleted job could still be around if we haven't gotten the job_delete yet
+                set_jobs(old_jobs => old_jobs.filter(j => !(j.id == event.job_create.id && j.user == event.job_create.user)).concat([event.job_create]));
+            if ("job_delete" in event)
+                set_jobs(old_jobs => old_jobs.filter(j => !(j.id == id && j.user == user)));
             if ("job_update" in event)
                 update_job((j) => Object.assign(j, event.job_update));
             if ("run_create" in event)
@@ -225,11 +297,15 @@ function jobs_view({jobs_url, runs_url, set_view}) {
     }, [jobs, set_prune_state]);
 
     return jsr([card, { kind: "jobs-view", title: "Jobs",
-                        extra_header: ['a', { href: "#", onClick: prevent_default(() => set_show_settings(true)) },
-                                       svg.Settings] },
+                        extra_header: can(account, "admin") && ['a', { href: "#", onClick: prevent_default(() => set_show_settings(true)) },
+                                                                svg.Settings] },
                 show_settings && [global_settings, { jobs, close_settings: (reason) => { set_show_settings(false);
                                                                                          if (reason == Saved) prune() } }],
                 prune_state && [prune_modal, { prune_state, done: () => set_prune_state(undefined) }],
+                undoable && ["div", { className: "alert alert-warning" },
+                             `Deleted ${undoable.user} / ${undoable.name}. It'll be gone for good in a day. `,
+                             ["a", { href: "#", onClick: prevent_default(async () => { await _fetch(undoable.undelete_url, { method: "POST" });
+                                                                                        set_undoable(undefined) }) }, "Undo"]],
                     jobs == null ? [loading]
                                  : [["table", { className: "jobs" },
                                      ["thead",
@@ -292,7 +368,10 @@ function success_chart({success_url, last_run_at, last_run_status}) {
         ctx.fillRect(0,0,canvas.width,canvas.height);
         if (!successes) return;
         let success = getComputedStyle(window.document.body).getPropertyValue('--bs-success');
+        let warning = getComputedStyle(window.document.body).getPropertyValue('--bs-warning');
         let failure = getComputedStyle(window.document.body).getPropertyValue('--bs-danger');
+        // Older servers send true/false instead of an outcome
+        const outcome = (r) => r == true ? "success" : r == false ? "failure" : r;
         let days = 30;
         let day = Array.from(Array(days)).map(_=>[]);
         let ms__day = 24*3600*1000;
@@ -315,9 +394,10 @@ function success_chart({success_url, last_run_at, last_run_status}) {
                 let sub_gap = h.length <= pixels/2 ? 1 : 0; // If we can fit a border around each entry, add one.
                 let x__run = (pixels + sub_gap) / h.length; // this + is counter-intuitive to me! But I worked it out on paper.
                 for (let [j, r] of h.entries()) {
-                    ctx.fillStyle = r[1] == undefined ? "#444" :
-                                    r[1] == true      ? success :
-                                    r[1] == false     ? failure : "pink";
+                    ctx.fillStyle = r[1] == undefined            ? "#444"  :
+                                    outcome(r[1]) == "success"   ? success :
+                                    outcome(r[1]) == "warning"   ? warning :
+                                    outcome(r[1]) == "failure"   ? failure : "pink";
                     let width = (start_px + Math.round((j+1)*x__run)) - (start_px + Math.round(j*x__run)) - sub_gap;
                     ctx.fillRect(start_px + Math.round(j*x__run), 0, width, canvas.height);
                 }
@@ -326,16 +406,16 @@ function success_chart({success_url, last_run_at, last_run_status}) {
                 let px__run = pixels / h.length;
                 for (let [j, r] of h.entries()) {
                     let p = Math.floor(j*px__run);
-                    // This table prioritizes failures and de-prioritizes gaps (undefined)
-                    px[p] = px[p] == undefined             ? r[1]  :
-                            px[p] == true && r[1] == false ? false :
-                            px[p] == true                  ? true  :
-                            px[p] == false                 ? px[p] : (_=>{debugger;throw "can't happen"})();
+                    // This prioritizes failures, then warnings, and de-prioritizes gaps (undefined)
+                    const rank = { success: 1, warning: 2, failure: 3 };
+                    let o = r[1] == undefined ? undefined : outcome(r[1]);
+                    px[p] = px[p] == undefined || rank[o] > rank[px[p]] ? o : px[p];
                 }
-                for (let [x, succ] of px.entries()) {
-                    ctx.fillStyle = succ == undefined ? "#444" :
-                                    succ == true      ? success :
-                                    succ == false     ? failure : "pink";
+                for (let [x, o] of px.entries()) {
+                    ctx.fillStyle = o == undefined ? "#444"  :
+                                    o == "success" ? success :
+                                    o == "warning" ? warning :
+                                    o == "failure" ? failure : "pink";
                     ctx.fillRect(start_px + x, 0, 1, canvas.height);
                 }
             }
@@ -344,6 +424,56 @@ function success_chart({success_url, last_run_at, last_run_status}) {
     return jsr(['canvas', { ref: _=>canvas_ref.current=_ }]);
 }
 
+// The numbers the run reported with `syncron metric`, each next to a chart of what the job's runs reported over the
+// last 30 days (this run is the dot) so it's easy to see when one is way off.
+function metrics_table({metrics, metrics_url, run_date}) {
+    let [series, set_series] = React.useState(null);
+
+    React.useEffect(() => {
+        if (!metrics_url) return; // Older server
+        let cancelled = false;
+        (async () => {
+            let series = await fetch_json(url_with(metrics_url, {after: Math.min(run_date, Date.now() - 30*24*3600*1000)}))
+            if (!cancelled) set_series(series);
+        })();
+        return () => cancelled = true;
+    }, [metrics_url, run_date]);
+
+    return jsr(["table", { className: "metrics" },
+                ["tbody", Object.entries(metrics).map(([name, value]) =>
+                    ["tr", { key: name },
+                     ["td", ["code", name]],
+                     ["td", { className: "value" }, value.toLocaleString()],
+                     ["td", { className: "chart" }, series && [metric_chart, { points: series.filter(([_, m]) => name in m).map(([date, m]) => [date, m[name]]), run_date }]]])]]);
+}
+
+function metric_chart({points, run_date}) {
+    let canvas_ref = use_canvas((ctx, canvas) => {
+        if (canvas.width != canvas.clientWidth) canvas.width = canvas.clientWidth;
+        if (canvas.height != canvas.clientHeight) canvas.height = canvas.clientHeight;
+        ctx.clearRect(0,0,canvas.width,canvas.height);
+        if (points.length == 0) return;
+        let [first, last] = [points[0][0], points[points.length-1][0]];
+        let [min, max] = [Math.min(0, ...points.map(p => p[1])), Math.max(...points.map(p => p[1]))];
+        let pad = 3;
+        let x = (date)  => pad + (last == first ? (canvas.width-2*pad)/2 : (date - first) / (last - first) * (canvas.width-2*pad));
+        let y = (value) => canvas.height - pad - (max == min ? 0 : (value - min) / (max - min) * (canvas.height-2*pad));
+        ctx.strokeStyle = getComputedStyle(window.document.body).getPropertyValue('--bs-secondary');
+        ctx.beginPath();
+        for (let [date, value] of points)
+            ctx.lineTo(x(date), y(value));
+        ctx.stroke();
+        let run = points.find(p => p[0] == run_date);
+        if (run) {
+            ctx.fillStyle = getComputedStyle(window.document.body).getPropertyValue('--bs-primary');
+            ctx.beginPath();
+            ctx.arc(x(run[0]), y(run[1]), pad, 0, 2*Math.PI);
+            ctx.fill();
+        }
+    }, [points, run_date]);
+    return jsr(['canvas', { ref: _=>canvas_ref.current=_ }]);
+}
+
 function use_canvas(draw, deps) {
     let canvas_ref = React.useRef(null);
 
@@ -356,7 +486,7 @@ function use_canvas(draw, deps) {
     return canvas_ref;
 }
 
-function runs_view({runs_url, job, set_view}) {
+function runs_view({runs_url, job, set_view, account}) {
     let [runs, set_runs] = React.useState(null);
     let [show_settings, set_show_settings] = React.useState(false);
     let [prune_state, set_prune_state] = React.useState(undefined);
@@ -411,9 +541,18 @@ function runs_view({runs_url, job, set_view}) {
         set_prune_state({ pruning: false, result });
     };
 
+    const delete_job = async () => {
+        if (!confirm(`Delete ${job.user} / ${job.name} and all of its runs?`)) return;
+        await _fetch(job.url, { method: "DELETE" });
+        set_view({ view: "jobs", deleted: job });
+    };
+
     return jsr([card, { kind: "runs-view", title: `${job.user} / ${job.name}`,
-                        extra_header: ['a', { href: "#", onClick: prevent_default(() => set_show_settings(true)) },
-                                       svg.Settings ] },
+                        extra_header: can(account, "operator") && [React.Fragment,
+                                                                   ['a', { href: "#", title: "Delete job", className: "delete-job", onClick: prevent_default(delete_job) },
+                                                                    svg.Delete ],
+                                                                   ['a', { href: "#", onClick: prevent_default(() => set_show_settings(true)) },
+                                                                    svg.Settings ]] },
                 show_settings && [job_settings, { job, close_settings: (reason) => { set_show_settings(false);
                                                                                      if (reason == Saved) prune() } }],
                 prune_state && [prune_modal, { job, prune_state, done: () => set_prune_state(undefined) }],
@@ -579,12 +718,40 @@ function log_view({run_url, job, run_id}) {
     return jsr([card, { kind: "log-view",
                         title: [React.Fragment, svg[status], ` ${job.user} / ${job.name} on ${run ? localiso(run.date) : "…"}`] },
                     !run ? [loading]
-                         : [["h2", "Command:"], ["code", run.cmd],
+                         : [run.summary && ["div", { className: "summary", dangerouslySetInnerHTML: { __html: run.summary } }], // The server renders it without any raw HTML
+                            ["h2", "Command:"], ["code", run.cmd],
+                            run.scheduled != undefined && [React.Fragment,
+                                                           ["h2", "Scheduled:"],
+                                                           ["p", { className: "scheduled" },
+                                                            `${localiso(run.scheduled)}, started ${elapsed(Math.floor((run.date - run.scheduled)/1000), true)} later (--splay)`]],
                             ["div", { className: `env ${show_env ? "show" : "hide"}` },
                              ["h2", { onClick: prevent_default(() => set_show_env(!show_env)) }, "Environment:"],
                              ["table",
                               ["tbody", run.env.map(([k,v]) => ["tr", ["td", ["code", k]], ["td", ["code", v]]])]]],
+                            run.usage && [React.Fragment,
+                                          ["h2", "Resources:"],
+                                          ["p", { className: "usage" },
+                                           `${(run.usage.user_ms/1000).toFixed(2)}s user, ${(run.usage.system_ms/1000).toFixed(2)}s system, `,
+                                           `${human_bytes(run.usage.max_rss)} max RSS, `,
+                                           `${run.usage.block_in} blocks in, ${run.usage.block_out} blocks out, `,
+                                           `${run.usage.voluntary_switches + run.usage.involuntary_switches} context switches`]],
+                            run.metrics && Object.keys(run.metrics).length > 0 && [React.Fragment,
+                                                                                   ["h2", "Metrics:"],
+                                                                                   [metrics_table, { metrics: run.metrics, metrics_url: job.metrics_url, run_date: run.date }]],
+                            run.artifacts?.length > 0 && [React.Fragment,
+                                                          ["h2", "Artifacts:"],
+                                                          ["ul", { className: "artifacts" },
+                                                           run.artifacts.map(a => ["li", { key: a.name },
+                                                                                   ["a", { href: a.url, target: "_blank" }, a.name],
+                                                                                   ` (${human_bytes(a.size)})`])]],
                             ["h2", "Output:"],
+                            run.log_gaps?.length > 0 && ["p", { className: "log-gaps" },
+                                                         ["em", `${human_bytes(run.log_gaps.reduce((sum, gap) => sum + gap.missing, 0))} of output never made it to the server.`]],
+                            run.log_matches?.length > 0 && ["ul", { className: "log-matches" },
+                                                            run.log_matches.map(m => ["li", { key: m.line, className: m.level },
+                                                                                      ["a", { href: url_with(run.log_url, { seek: m.offset }), target: "_blank" }, `Line ${m.line}`],
+                                                                                      m.level == "failure" ? " (failed): " : " (warning): ",
+                                                                                      ["code", m.text]])],
                             ["pre", ...format_log(run.log||[]), "\n", status == 'Running' ? ["div", { className: "dot-flashing" }] : human_status(run.status)]
                            ]]);
 }
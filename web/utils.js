@@ -58,6 +58,7 @@ export async function _fetch(url, options={}) {
         headers = { headers: { "Content-Type": "application/json", ...options.headers ?? {} } };
     try {
         let resp = await window.fetch(url, {...options, ...headers });
+        if (resp.status == 401) window.location.reload(); // The session expired (or someone logged us out). Back to the login page.
         if (!resp.ok) throw("Response failed: "+resp.statusText)
         return resp;
     } catch(e) {